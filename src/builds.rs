@@ -1,3 +1,5 @@
+    use crate::ylab::{self, YLabVersion};
+
     const N: usize = 8;
 
     /// A board with baud rate
@@ -22,6 +24,35 @@
             let out = format!("{:?}", *self);
             return out.into();
         }
+
+        /// Sample interval in seconds, None for event sensories (MOI, AIR)
+        pub fn interval(&self) -> Option<f32> {
+            match *self {
+                Sensory::ADC(interval, _) | Sensory::ACC(interval, _) | Sensory::YXZ(interval, _) => Some(interval),
+                Sensory::MOI(_) | Sensory::AIR(_) => None,
+            }
+        }
+
+        /// The sensory as the YLab thread reads it, rates in Hz
+        pub fn to_ylab(&self) -> ylab::Sensory {
+            let n = self.n_active() as u8;
+            let hz = self.interval().map_or(0, |interval| (1.0 / interval).round() as usize);
+            match *self {
+                Sensory::MOI(_) => ylab::Sensory::Moi(n),
+                Sensory::AIR(_) => ylab::Sensory::Air(n),
+                Sensory::ADC(..) => ylab::Sensory::Adc(n, hz),
+                Sensory::ACC(..) | Sensory::YXZ(..) => ylab::Sensory::Yxz(n, hz),
+            }
+        }
+
+        /// Number of active channels
+        pub fn n_active(&self) -> usize {
+            match *self {
+                Sensory::MOI(chans) | Sensory::AIR(chans) 
+                | Sensory::ADC(_, chans) | Sensory::ACC(_, chans) | Sensory::YXZ(_, chans) 
+                => chans.iter().filter(|chan| chan.is_some()).count(),
+            }
+        }
     }
     
     /// Build
@@ -92,5 +123,37 @@
             None, None, None, None, None
             ]),
         ];
-    
-    
\ No newline at end of file
+
+    impl Build {
+        pub fn name(&self) -> &'static str {
+            self.0
+        }
+
+        pub fn baud(&self) -> usize {
+            self.1.0
+        }
+
+        /// Sensories by bank number
+        pub fn sensories(&self) -> &[Option<Sensory>; N] {
+            &self.2
+        }
+
+        /// Sensories of the banks in use, as the YLab thread reads them
+        pub fn layout(&self) -> Vec<ylab::Sensory> {
+            self.2.iter().map_while(|sensory| sensory.map(|sensory| sensory.to_ylab())).collect()
+        }
+
+        /// The YLab version that reads this build
+        pub fn version(&self) -> YLabVersion {
+            match self.0 {
+                "Pro Zero" => YLabVersion::Pro,
+                "Go Emo" => YLabVersion::GoStress,
+                "Go Mo" => YLabVersion::GoMotion(1),
+                // by the number of Yxz banks
+                name if name.starts_with("Go Mo") => YLabVersion::GoMotion(self.2.iter().flatten()
+                    .filter(|sensory| matches!(sensory, Sensory::YXZ(..)))
+                    .count() as u8),
+                _ => YLabVersion::Go,
+            }
+        }
+    }
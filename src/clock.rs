@@ -0,0 +1,217 @@
+/// Clock synchronisation
+///
+/// YLabs time-stamp every sample with their own clock (ms since startup).
+/// Ystudio maps device time onto its own time line (`ystudio_time`),
+/// so that recordings keep the precise sampling clock of the device,
+/// but can still be aligned with events on the host.
+///
+/// Transport only ever adds delay, so the smallest difference between
+/// arrival and device time is the best estimate of the clock offset.
+/// The minimum is taken per block of device time, and a line is fitted
+/// through the block minima to correct for drift between the clocks.
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Start of the Ystudio clock
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Time since start of Ystudio (the first call)
+///
+/// This is the one clock for arrival times, mapped device times and markers.
+pub fn ystudio_time() -> Duration {
+    START.get_or_init(Instant::now).elapsed()
+}
+
+/// Host clock of Lab Streaming Layer (LSL) at the start of the Ystudio clock
+///
+/// LSL time stamps are seconds of the monotonic clock of the host.
+/// Adding this to Ystudio times puts them on the LSL time line,
+/// so that exports line up with recordings of LabRecorder.
+/// None where the clock can not be read, and off Linux,
+/// where it is not read yet (exports then stay on the Ystudio clock).
+pub fn lsl_clock_start() -> Option<f64> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // only reads the clock into `now`
+        let read = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        if read != 0 {
+            return None;
+        }
+        let lsl = now.tv_sec as f64 + now.tv_nsec as f64 * 1e-9;
+        Some(lsl - ystudio_time().as_secs_f64())
+    }
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// Length of a block of device time, in seconds
+const BLOCK_SECS: f64 = 1.0;
+/// Number of blocks the drift is estimated from
+const MAX_BLOCKS: usize = 300;
+/// A device clock going back by more than this has been restarted
+const RESTART_SECS: f64 = 1.0;
+
+/// Mapping between a device clock and the host clock
+#[derive(Debug, Clone, Default)]
+pub struct ClockSync {
+    block: Option<(f64, f64, f64)>, // current block: start, device time and offset at minimum
+    minima: VecDeque<(f64, f64)>,   // (device time, offset) of finished blocks
+    offset: f64,                    // fitted offset at device time 0, in seconds
+    drift: f64,                     // fitted change of offset per second
+    last_dev: Option<f64>,
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adding a sample's device time and its arrival time on the host
+    pub fn update(&mut self, dev_time: Duration, arrival: Duration) {
+        let dev = dev_time.as_secs_f64();
+        let offset = arrival.as_secs_f64() - dev;
+        if self.last_dev.is_some_and(|last| dev + RESTART_SECS < last) {
+            *self = Self::new();
+        }
+        self.last_dev = Some(dev);
+        match self.block {
+            Some((start, _, _)) if dev - start >= BLOCK_SECS => {
+                self.close_block();
+                self.block = Some((dev, dev, offset));
+            }
+            Some((start, _, min)) if offset < min => self.block = Some((start, dev, offset)),
+            Some(_) => {}
+            None => self.block = Some((dev, dev, offset)),
+        }
+        // until the drift can be fitted, the smallest offset is used
+        if self.minima.len() < 2 {
+            let block_min = self.block.map_or(f64::INFINITY, |(_, _, min)| min);
+            self.offset = self
+                .minima
+                .iter()
+                .map(|(_, min)| *min)
+                .fold(block_min, f64::min);
+            self.drift = 0.0;
+        }
+    }
+
+    /// Device time on the host time line
+    pub fn map(&self, dev_time: Duration) -> Duration {
+        let dev = dev_time.as_secs_f64();
+        Duration::from_secs_f64((dev + self.offset + self.drift * dev).max(0.0))
+    }
+
+    /// Drift of the device clock relative to the host, in parts per million
+    pub fn drift_ppm(&self) -> f64 {
+        self.drift * 1e6
+    }
+
+    fn close_block(&mut self) {
+        if let Some((_, dev, min)) = self.block.take() {
+            self.minima.push_back((dev, min));
+            if self.minima.len() > MAX_BLOCKS {
+                self.minima.pop_front();
+            }
+        }
+        if self.minima.len() >= 2 {
+            self.fit();
+        }
+    }
+
+    /// Least squares line through the block minima
+    fn fit(&mut self) {
+        let n = self.minima.len() as f64;
+        let mean_x = self.minima.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = self.minima.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let sxx: f64 = self.minima.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        let sxy: f64 = self
+            .minima
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum();
+        self.drift = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+        self.offset = mean_y - self.drift * mean_x;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Transport delay of the i-th sample, 0 to 19 ms, every 20th without delay
+    fn delay(i: u64) -> f64 {
+        ((i * 7919) % 20) as f64 * 1e-3
+    }
+
+    /// Feeding samples at 100 Hz for `secs` seconds of device time,
+    /// arriving after the host clock has gone on by `rate` per device second
+    fn feed(clock: &mut ClockSync, from: f64, secs: f64, offset: f64, rate: f64) {
+        for i in 0..(secs * 100.0) as u64 {
+            let dev = from + i as f64 * 0.01;
+            let arrival = offset + dev * rate + delay(i);
+            clock.update(
+                Duration::from_secs_f64(dev),
+                Duration::from_secs_f64(arrival),
+            );
+        }
+    }
+
+    fn assert_near(got: Duration, want: f64, tolerance: f64) {
+        let got = got.as_secs_f64();
+        assert!(
+            (got - want).abs() < tolerance,
+            "{} instead of {}",
+            got,
+            want
+        );
+    }
+
+    #[test]
+    fn smallest_offset_before_fit() {
+        let mut clock = ClockSync::new();
+        feed(&mut clock, 10.0, 0.5, 3.0, 1.0);
+        assert_eq!(clock.drift_ppm(), 0.0);
+        assert_near(clock.map(Duration::from_secs(10)), 13.0, 1e-6);
+    }
+
+    #[test]
+    fn constant_offset() {
+        let mut clock = ClockSync::new();
+        feed(&mut clock, 0.0, 60.0, 2.0, 1.0);
+        assert!(clock.drift_ppm().abs() < 1.0, "{} ppm", clock.drift_ppm());
+        assert_near(clock.map(Duration::from_secs(30)), 32.0, 1e-3);
+    }
+
+    #[test]
+    fn drift_is_fitted() {
+        // the device clock is slow by 100 ppm
+        let mut clock = ClockSync::new();
+        feed(&mut clock, 0.0, 120.0, 0.5, 1.0 + 100e-6);
+        assert!(
+            (clock.drift_ppm() - 100.0).abs() < 5.0,
+            "{} ppm",
+            clock.drift_ppm()
+        );
+        // without the drift, the end would be off by 12 ms
+        assert_near(
+            clock.map(Duration::from_secs(120)),
+            0.5 + 120.0 * (1.0 + 100e-6),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn restart_resets_the_fit() {
+        let mut clock = ClockSync::new();
+        feed(&mut clock, 100.0, 30.0, 1.0, 1.0 + 100e-6);
+        // device restarted, its clock begins at 0 again
+        feed(&mut clock, 0.0, 0.5, 200.0, 1.0);
+        assert_eq!(clock.drift_ppm(), 0.0);
+        assert_near(clock.map(Duration::ZERO), 200.0, 1e-6);
+    }
+}
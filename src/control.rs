@@ -0,0 +1,399 @@
+/// Remote control
+///
+/// Experiment runners on the same computer drive Ystudio
+/// without the GUI: connect a YLab, start and stop recordings
+/// and ask for the current state.
+/// Clients connect to localhost over TCP (port 6532 by default),
+/// send one JSON request per line and get one JSON answer per line:
+///
+/// `{"cmd":"connect","port":"Simulator","version":"Go"}`
+/// `{"cmd":"connect","port":"/dev/ttyACM0"}` (detecting the version)
+/// `{"cmd":"record","dir":"/data","file":"p01.yld","participant":"p01"}`
+/// `{"cmd":"record","dir":"/data","compression":"zstd","segment_duration":"1h"}`
+/// `{"cmd":"stop"}`
+///
+/// Answers are `{"ok":true,"status":{...}}`, with the states after
+/// the command took effect, or `{"ok":false,"error":"..."}`.
+/// Commands are the same as in the GUI (`YLabCmd`, `YldestCmd`)
+/// and only accepted in the states where the GUI offers them.
+use crate::detect::max_detect_time;
+use crate::meta::Meta;
+use crate::segments::Rotation;
+use crate::sinks::{Compression, Format};
+use crate::ylab::{YLabCmd, YLabState, YLabVersion};
+use crate::yldest::{YldestCmd, YldestState};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default port for remote control
+pub const CONTROL_PORT: u16 = 6532;
+/// How long to wait for a command to take effect
+const TRANSITION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Shared states and command channels of YLab and Yldest
+#[derive(Clone)]
+pub struct Controls {
+    pub ylab_state: Arc<Mutex<YLabState>>,
+    pub ylab_cmd: Sender<YLabCmd>,
+    pub yldest_state: Arc<Mutex<YldestState>>,
+    pub yldest_cmd: Sender<YldestCmd>,
+}
+
+/// A request from a client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    Status,
+    Connect {
+        port: String,
+        // detected from the traffic, if not given
+        #[serde(default)]
+        version: Option<String>,
+    },
+    Disconnect,
+    Replay {
+        path: PathBuf,
+        version: String,
+        #[serde(default = "one")]
+        speed: f64,
+        #[serde(default, rename = "loop")]
+        looping: bool,
+    },
+    Record {
+        dir: Option<PathBuf>,
+        file: Option<PathBuf>,
+        format: Option<String>,
+        compression: Option<String>,
+        segment_mb: Option<u64>,
+        segment_duration: Option<String>,
+        #[serde(default)]
+        participant: String,
+        #[serde(default)]
+        session: String,
+        #[serde(default)]
+        notes: String,
+    },
+    Pause,
+    Resume,
+    Stop,
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+/// Serving remote control on localhost
+///
+/// Returns once the port is bound, serving goes on in threads.
+pub fn serve_control(port: u16, controls: Controls) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let controls = controls.clone();
+            thread::spawn(move || client_thread(stream, controls));
+        }
+    });
+    Ok(())
+}
+
+/// Answering requests of one client, until it disconnects
+fn client_thread(stream: TcpStream, controls: Controls) {
+    let Ok(mut reply) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = match serde_json::from_str::<Request>(&line) {
+            Err(e) => Err(format!("invalid request: {}", e)),
+            Ok(request) => controls.handle(request),
+        };
+        let answer = match answer {
+            Ok(()) => json!({"ok": true, "status": controls.status()}),
+            Err(error) => json!({"ok": false, "error": error}),
+        };
+        if writeln!(reply, "{}", answer).is_err() {
+            return;
+        }
+    }
+}
+
+/// Waiting until a shared state fulfills a condition
+fn wait_for<S>(state: &Arc<Mutex<S>>, cond: impl Fn(&S) -> bool) -> Result<(), String> {
+    wait_within(state, TRANSITION_TIMEOUT, cond)
+}
+
+/// Waiting as `wait_for`, with a timeout of its own
+fn wait_within<S>(
+    state: &Arc<Mutex<S>>,
+    timeout: Duration,
+    cond: impl Fn(&S) -> bool,
+) -> Result<(), String> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if cond(&state.lock().unwrap()) {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Err("timed out".to_string())
+}
+
+/// Waiting until a thread has taken the commands sent before
+///
+/// The thread answers a `Sync` command after the commands before it,
+/// or drops it unanswered, which also means it got that far.
+fn sync_with(send: impl FnOnce(Sender<()>) -> Result<(), String>) -> Result<(), String> {
+    let (ack, acked) = mpsc::channel();
+    send(ack)?;
+    match acked.recv_timeout(TRANSITION_TIMEOUT) {
+        Err(RecvTimeoutError::Timeout) => Err("timed out".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// The reason, if the YLab has failed
+fn ylab_failure(state: &Arc<Mutex<YLabState>>) -> Result<(), String> {
+    match state.lock().unwrap().clone() {
+        YLabState::Failed { reason, .. } => Err(reason),
+        _ => Ok(()),
+    }
+}
+
+/// The reason, if recording has failed
+fn yldest_failure(state: &Arc<Mutex<YldestState>>) -> Result<(), String> {
+    match state.lock().unwrap().clone() {
+        YldestState::Failed { reason, .. } => Err(reason),
+        _ => Ok(()),
+    }
+}
+
+impl Controls {
+    /// Current states of YLab and Yldest, as JSON
+    pub fn status(&self) -> Value {
+        let ylab = match self.ylab_state.lock().unwrap().clone() {
+            YLabState::Disconnected { ports } => json!({"state": "disconnected", "ports": ports}),
+            YLabState::Connected { version, port_name } => {
+                json!({"state": "connected", "version": version.to_string(), "port": port_name})
+            }
+            YLabState::Reading { version, port_name } => {
+                json!({"state": "reading", "version": version.to_string(), "port": port_name})
+            }
+            YLabState::Detecting { port_name } => json!({"state": "detecting", "port": port_name}),
+            YLabState::Reconnecting {
+                version,
+                port_name,
+                reason,
+            } => json!({"state": "reconnecting", "version": version.to_string(),
+                        "port": port_name, "reason": reason}),
+            YLabState::Failed {
+                version,
+                port_name,
+                reason,
+            } => json!({"state": "failed", "version": version.to_string(),
+                        "port": port_name, "reason": reason}),
+        };
+        let yldest = match self.yldest_state.lock().unwrap().clone() {
+            YldestState::Idle { dir } => json!({"state": "idle", "dir": dir}),
+            YldestState::Connected { path } => json!({"state": "paused", "path": path}),
+            YldestState::Recording { path } => json!({"state": "recording", "path": path}),
+            YldestState::Failed { dir, reason } => {
+                json!({"state": "failed", "dir": dir, "reason": reason})
+            }
+        };
+        json!({"ylab": ylab, "yldest": yldest})
+    }
+
+    /// Checking a request against the current states and sending it on
+    fn handle(&self, request: Request) -> Result<(), String> {
+        let ylab = self.ylab_state.lock().unwrap().clone();
+        let yldest = self.yldest_state.lock().unwrap().clone();
+        match request {
+            Request::Status => Ok(()),
+            Request::Connect { port, version } => {
+                let version = version.map(|v| v.parse::<YLabVersion>()).transpose()?;
+                // the YLab thread accepts serial ports once it has listed them,
+                // or again after a failure
+                wait_for(&self.ylab_state, |s| {
+                    matches!(
+                        s,
+                        YLabState::Disconnected { ports: Some(_) } | YLabState::Failed { .. }
+                    )
+                })
+                .map_err(|_| "YLab is not disconnected".to_string())?;
+                self.send_ylab(match version {
+                    Some(version) => YLabCmd::Connect {
+                        version,
+                        port_name: port.clone(),
+                    },
+                    None => YLabCmd::Detect {
+                        port_name: port.clone(),
+                    },
+                })?;
+                // a retry may fail again, so the old failure must be gone
+                self.sync_ylab()?;
+                // detection tries every baud rate
+                let timeout = match version {
+                    Some(_) => TRANSITION_TIMEOUT,
+                    None => TRANSITION_TIMEOUT + max_detect_time(),
+                };
+                wait_within(&self.ylab_state, timeout, |s| {
+                    matches!(s, YLabState::Reading { .. } | YLabState::Failed { .. })
+                })
+                .map_err(|_| format!("could not connect to {}", port))?;
+                ylab_failure(&self.ylab_state)
+            }
+            Request::Replay {
+                path,
+                version,
+                speed,
+                looping,
+            } => {
+                let version = version.parse::<YLabVersion>()?;
+                if !matches!(
+                    ylab,
+                    YLabState::Disconnected { .. } | YLabState::Failed { .. }
+                ) {
+                    return Err("YLab is not disconnected".to_string());
+                }
+                if !path.is_file() {
+                    return Err(format!("no such file: {:?}", path));
+                }
+                self.send_ylab(YLabCmd::Replay {
+                    version,
+                    path: path.clone(),
+                    speed,
+                    looping,
+                })?;
+                self.sync_ylab()?;
+                wait_for(&self.ylab_state, |s| {
+                    matches!(s, YLabState::Reading { .. } | YLabState::Failed { .. })
+                })
+                .map_err(|_| format!("could not replay {:?}", path))?;
+                ylab_failure(&self.ylab_state)
+            }
+            Request::Disconnect => {
+                if matches!(ylab, YLabState::Disconnected { .. }) {
+                    return Err("YLab is not connected".to_string());
+                }
+                self.send_ylab(YLabCmd::Disconnect)?;
+                wait_for(&self.ylab_state, |s| {
+                    matches!(s, YLabState::Disconnected { .. })
+                })?;
+                // a running recording goes to disk
+                self.send_yldest(YldestCmd::Flush)
+            }
+            Request::Record {
+                dir,
+                file,
+                format,
+                compression,
+                segment_mb,
+                segment_duration,
+                participant,
+                session,
+                notes,
+            } => {
+                let YLabState::Reading { version, port_name } = ylab else {
+                    return Err("YLab is not reading".to_string());
+                };
+                if !matches!(
+                    yldest,
+                    YldestState::Idle { .. } | YldestState::Failed { .. }
+                ) {
+                    return Err("already recording".to_string());
+                }
+                if dir.as_ref().is_some_and(|dir| !dir.is_dir()) {
+                    return Err(format!("no such directory: {:?}", dir.unwrap()));
+                }
+                // by name, otherwise by the extension of the file
+                let format = match (format, file.as_ref()) {
+                    (Some(format), _) => format.parse::<Format>()?,
+                    (None, Some(file)) => Format::from_path(file),
+                    (None, None) => Format::Yld,
+                };
+                let compression = match (compression, file.as_ref()) {
+                    (Some(compression), _) => compression.parse::<Compression>()?,
+                    (None, Some(file)) => Compression::from_path(file),
+                    (None, None) => Compression::None,
+                };
+                let rotation = Rotation {
+                    max_bytes: segment_mb.map(|mb| mb * 1_000_000),
+                    max_duration: segment_duration
+                        .map(|d| humantime::parse_duration(&d))
+                        .transpose()
+                        .map_err(|e| format!("invalid segment_duration: {}", e))?,
+                };
+                let meta = Meta {
+                    participant,
+                    session,
+                    notes,
+                    ..Meta::new(version, &port_name)
+                };
+                self.send_yldest(YldestCmd::New {
+                    change_dir: dir,
+                    file_name: file,
+                    format,
+                    compression,
+                    rotation,
+                    meta: Box::new(meta),
+                })?;
+                // the recording is created, or has failed, once the command is taken
+                self.sync_yldest()?;
+                yldest_failure(&self.yldest_state)
+            }
+            Request::Pause => {
+                if !matches!(yldest, YldestState::Recording { .. }) {
+                    return Err("not recording".to_string());
+                }
+                self.send_yldest(YldestCmd::Pause)?;
+                self.sync_yldest()?;
+                yldest_failure(&self.yldest_state)
+            }
+            Request::Resume => {
+                if !matches!(yldest, YldestState::Connected { .. }) {
+                    return Err("not paused".to_string());
+                }
+                self.send_yldest(YldestCmd::Record)?;
+                self.sync_yldest()
+            }
+            Request::Stop => {
+                if matches!(yldest, YldestState::Idle { .. }) {
+                    return Err("not recording".to_string());
+                }
+                // a failure is dismissed, a recording closed or failing while closing
+                self.send_yldest(YldestCmd::Stop)?;
+                self.sync_yldest()?;
+                yldest_failure(&self.yldest_state)
+            }
+        }
+    }
+
+    fn send_ylab(&self, cmd: YLabCmd) -> Result<(), String> {
+        self.ylab_cmd
+            .send(cmd)
+            .map_err(|_| "YLab thread has ended".to_string())
+    }
+
+    fn send_yldest(&self, cmd: YldestCmd) -> Result<(), String> {
+        self.yldest_cmd
+            .send(cmd)
+            .map_err(|_| "Yldest thread has ended".to_string())
+    }
+
+    fn sync_ylab(&self) -> Result<(), String> {
+        sync_with(|ack| self.send_ylab(YLabCmd::Sync(ack)))
+    }
+
+    fn sync_yldest(&self) -> Result<(), String> {
+        sync_with(|ack| self.send_yldest(YldestCmd::Sync(ack)))
+    }
+}
@@ -0,0 +1,216 @@
+/// Detecting the YLab version
+///
+/// YLab firmware sends without being asked and does not answer queries,
+/// so the build is told from the first seconds of traffic instead:
+/// which sensory banks send, at what rate and on how many channels.
+/// Of the firmware builds (see `builds::BUILDS`) that fit the traffic,
+/// the one with the fewest banks and channels is taken,
+/// e.g. Go Zero rather than Pro Zero for three ADC channels at 500 Hz.
+///
+/// YLabs with native USB take any baud rate, but boards with a UART bridge
+/// (CP210x, CH9102) only send readable data at the rate of their build.
+/// So the port is tried at the baud rates of the builds in turn (see `bauds`),
+/// until the traffic fits a build.
+use crate::builds::{Build, BUILDS};
+use crate::ylab::data::Ytf8;
+use crate::ylab::YLabVersion;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Time the traffic is watched
+pub const DETECT_TIME: Duration = Duration::from_secs(3);
+/// Version named in the states before it is known, e.g. in `Failed`
+pub const DETECT_VERSION: YLabVersion = YLabVersion::Go;
+/// Measured rates may be off by this much (of the build's rate)
+const RATE_TOLERANCE: f64 = 0.25;
+
+/// Traffic of one sensory bank
+#[derive(Debug, Clone, Default)]
+struct BankTraffic {
+    samples: u64,
+    first: Option<Duration>, // device time
+    last: Option<Duration>,
+    channels: usize, // highest channel with a reading, plus one
+}
+
+impl BankTraffic {
+    /// Samples per second of device time
+    fn rate(&self) -> Option<f64> {
+        let span = (self.last? - self.first?).as_secs_f64();
+        (span > 0.0).then(|| (self.samples - 1) as f64 / span)
+    }
+}
+
+/// Longest time detection takes, watching the traffic at every baud rate
+pub fn max_detect_time() -> Duration {
+    DETECT_TIME * bauds().len() as u32
+}
+
+/// Baud rates of the builds, the most common first
+pub fn bauds() -> Vec<u32> {
+    let mut bauds: Vec<(u32, usize)> = vec![];
+    for build in BUILDS.iter() {
+        let baud = build.baud() as u32;
+        match bauds.iter_mut().find(|(known, _)| *known == baud) {
+            Some((_, n)) => *n += 1,
+            None => bauds.push((baud, 1)),
+        }
+    }
+    bauds.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    bauds.into_iter().map(|(baud, _)| baud).collect()
+}
+
+/// Collecting traffic and matching it with the firmware builds
+#[derive(Debug, Clone)]
+pub struct Detector {
+    started: Instant,
+    banks: BTreeMap<u8, BankTraffic>,
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Detector {
+            started: Instant::now(),
+            banks: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, sample: &Ytf8) {
+        let bank = self.banks.entry(sample.sensory).or_default();
+        bank.samples += 1;
+        bank.first.get_or_insert(sample.time);
+        bank.last = Some(sample.time);
+        let channels = sample
+            .read
+            .iter()
+            .rposition(|value| *value != 0.0)
+            .map_or(0, |chan| chan + 1);
+        bank.channels = bank.channels.max(channels);
+    }
+
+    /// Has the traffic been watched long enough?
+    pub fn is_done(&self) -> bool {
+        self.started.elapsed() >= DETECT_TIME
+    }
+
+    /// Does the traffic fit a build?
+    ///
+    /// Every bank that sends must be in the build, with no more channels,
+    /// and at its rate if it is sampled. Sampled banks of the build must send,
+    /// event banks (MOI, AIR) may stay silent.
+    pub fn fits(&self, build: &Build) -> bool {
+        let sent = self.banks.iter().all(|(bank, traffic)| {
+            let Some(Some(sensory)) = build.sensories().get(*bank as usize) else {
+                return false;
+            };
+            let rate_fits = match (sensory.interval(), traffic.rate()) {
+                (None, _) => true,
+                (Some(interval), Some(rate)) => {
+                    let hz = 1.0 / interval as f64;
+                    (rate - hz).abs() <= hz * RATE_TOLERANCE
+                }
+                (Some(_), None) => false,
+            };
+            traffic.channels <= sensory.n_active() && rate_fits
+        });
+        let sampled_send = build
+            .sensories()
+            .iter()
+            .enumerate()
+            .filter(|(_, sensory)| sensory.is_some_and(|sensory| sensory.interval().is_some()))
+            .all(|(bank, _)| self.banks.contains_key(&(bank as u8)));
+        !self.banks.is_empty() && sent && sampled_send
+    }
+
+    /// The build that fits best, if any
+    pub fn detect(&self) -> Option<&'static Build> {
+        BUILDS
+            .iter()
+            .filter(|build| self.fits(build))
+            .min_by_key(|build| {
+                let sensories = build.sensories().iter().flatten();
+                let channels: usize = sensories.clone().map(|sensory| sensory.n_active()).sum();
+                (sensories.count(), channels)
+            })
+    }
+
+    /// What was seen, for telling the user, e.g. "bank 1: 3 channels at 498 Hz"
+    pub fn summary(&self) -> String {
+        if self.banks.is_empty() {
+            return "no samples".to_string();
+        }
+        self.banks
+            .iter()
+            .map(|(bank, traffic)| match traffic.rate() {
+                Some(rate) => format!(
+                    "bank {}: {} channels at {:.0} Hz",
+                    bank, traffic.channels, rate
+                ),
+                None => format!("bank {}: {} channels", bank, traffic.channels),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two seconds of traffic on a bank, `channels` of them reading
+    fn traffic(detector: &mut Detector, bank: u8, hz: f64, channels: usize) {
+        for n in 0..(2.0 * hz) as usize {
+            let mut sample = Ytf8 {
+                sensory: bank,
+                time: Duration::from_secs_f64(n as f64 / hz),
+                ..Default::default()
+            };
+            sample.read[..channels].fill(1.0);
+            detector.add(&sample);
+        }
+    }
+
+    #[test]
+    fn fewest_banks_and_channels_win() {
+        let mut detector = Detector::new();
+        traffic(&mut detector, 1, 500.0, 3);
+        assert_eq!(detector.detect().map(Build::name), Some("Go Zero"));
+        // more channels than a Go has
+        let mut detector = Detector::new();
+        traffic(&mut detector, 1, 500.0, 8);
+        assert_eq!(detector.detect().map(Build::name), Some("Pro Zero"));
+        assert_eq!(detector.summary(), "bank 1: 8 channels at 500 Hz");
+    }
+
+    #[test]
+    fn rates_and_banks_must_fit() {
+        let mut detector = Detector::new();
+        traffic(&mut detector, 1, 100.0, 3);
+        // Go Mo needs its motion bank
+        assert!(detector.detect().is_none());
+        traffic(&mut detector, 2, 200.0, 6);
+        assert_eq!(detector.detect().map(Build::name), Some("Go Mo"));
+        assert_eq!(
+            detector.detect().map(Build::version),
+            Some(YLabVersion::GoMotion(1))
+        );
+
+        let mut detector = Detector::new();
+        traffic(&mut detector, 1, 250.0, 3);
+        assert!(detector.detect().is_none());
+        assert!(Detector::new().detect().is_none());
+        assert_eq!(Detector::new().summary(), "no samples");
+    }
+
+    #[test]
+    fn bauds_of_the_builds_most_common_first() {
+        assert_eq!(bauds(), [1_000_000, 2_000_000]);
+        assert_eq!(max_detect_time(), DETECT_TIME * 2);
+    }
+}
@@ -0,0 +1,57 @@
+/// Signal processing
+///
+/// + low-pass filtering of channels, as used for the raw signal view
+/// + power spectra of channels, as used for the frequency view
+use biquad::{Biquad, Coefficients, DirectForm1, ToHertz, Type, Q_BUTTERWORTH_F32};
+use spectrum_analyzer::error::SpectrumAnalyzerError;
+use spectrum_analyzer::scaling::divide_by_N;
+use spectrum_analyzer::windows::hann_window;
+use spectrum_analyzer::{samples_fft_to_spectrum, FrequencyLimit};
+
+/// Number of samples the low-pass filter needs to settle
+pub fn lowpass_burnin(rate: f32, cutoff: f32) -> usize {
+    2 * (rate / cutoff) as usize + 1
+}
+
+/// Low-pass filtering a line of (time, value) points
+///
+/// Butterworth biquad at the sample rate of the line.
+/// The burn-in at the start of the line is removed.
+pub fn lowpass(
+    points: impl IntoIterator<Item = [f64; 2]>,
+    rate: f32,
+    cutoff: f32,
+) -> Result<Vec<[f64; 2]>, biquad::Errors> {
+    let coeffs =
+        Coefficients::<f32>::from_params(Type::LowPass, rate.hz(), cutoff.hz(), Q_BUTTERWORTH_F32)?;
+    let mut biquad_lpf = DirectForm1::<f32>::new(coeffs);
+    Ok(points
+        .into_iter()
+        .map(|point| [point[0], biquad_lpf.run(point[1] as f32) as f64])
+        .skip(lowpass_burnin(rate, cutoff))
+        .collect())
+}
+
+/// Power spectrum of one channel
+///
+/// The samples are Hann-windowed, their number must be a power of two.
+/// Returns (frequency, amplitude) pairs within the frequency range.
+pub fn spectrum(
+    samples: &[f32],
+    rate: u32,
+    min_freq: f32,
+    max_freq: f32,
+) -> Result<Vec<[f64; 2]>, SpectrumAnalyzerError> {
+    let hann_window = hann_window(samples);
+    let spectrum = samples_fft_to_spectrum(
+        &hann_window,
+        rate,
+        FrequencyLimit::Range(min_freq, max_freq),
+        Some(&divide_by_N),
+    )?;
+    Ok(spectrum
+        .data()
+        .iter()
+        .map(|(freq, ampl)| [freq.val() as f64, ampl.val() as f64])
+        .collect())
+}
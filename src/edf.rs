@@ -0,0 +1,621 @@
+/// EDF+ and BDF+ export
+///
+/// EEGLAB, MNE and EDFbrowser read EDF+ (16 bit) and BDF+ (24 bit).
+/// A recording is exported with one signal per channel of every
+/// sampled bank (ADC and Yxz), at the rate of the bank, in data records
+/// of one second. Physical ranges and units come from the calibration
+/// of the sensory, event markers become EDF annotations.
+///
+/// EDF signals are continuous: samples are placed by their time stamp,
+/// lost samples are filled with the previous value.
+/// Event banks (MOI, Air) have no rate and are not exported.
+///
+/// The recording is read twice, once for its span and once for writing,
+/// so only the data records being filled are held in memory.
+use crate::marks::Mark;
+use crate::meta::Meta;
+use crate::sinks;
+use crate::ylab::{Calibration, YLabVersion};
+use crate::yldfile::{for_each_sample, read_marks};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Duration of a data record, in seconds
+const RECORD_SECS: usize = 1;
+/// Separators of time-stamped annotation lists (TAL)
+const TAL_SEP: char = '\u{14}';
+const TAL_END: char = '\u{0}';
+/// Data records held back for samples arriving late
+const PENDING_RECORDS: usize = 2;
+
+/// EDF+ or BDF+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdfKind {
+    Edf,
+    Bdf,
+}
+
+impl EdfKind {
+    /// File extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EdfKind::Edf => "edf",
+            EdfKind::Bdf => "bdf",
+        }
+    }
+
+    /// Bytes per sample
+    fn width(&self) -> usize {
+        match self {
+            EdfKind::Edf => 2,
+            EdfKind::Bdf => 3,
+        }
+    }
+
+    /// Range of digital values
+    fn range(&self) -> (i32, i32) {
+        match self {
+            EdfKind::Edf => (-32768, 32767),
+            EdfKind::Bdf => (-8_388_608, 8_388_607),
+        }
+    }
+}
+
+impl fmt::Display for EdfKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdfKind::Edf => write!(f, "EDF"),
+            EdfKind::Bdf => write!(f, "BDF"),
+        }
+    }
+}
+
+impl FromStr for EdfKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "edf" | "edf+" => Ok(EdfKind::Edf),
+            "bdf" | "bdf+" => Ok(EdfKind::Bdf),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+/// One exported channel
+struct Signal {
+    label: String,
+    calibration: Calibration,
+    rate: usize, // samples per second
+    bank: u8,
+    chan: usize,
+}
+
+impl Signal {
+    /// Index of a sample from the start of the recording
+    fn index(&self, time: f64, start: f64) -> usize {
+        ((time - start) * self.rate as f64).round() as usize
+    }
+
+    fn per_record(&self) -> usize {
+        self.rate * RECORD_SECS
+    }
+}
+
+/// Exporting a recording (Yld or Parquet) with its markers
+///
+/// The YLab version is taken from the metadata of the recording,
+/// `version` is used for recordings without.
+/// Returns the number of signals and of data records written.
+pub fn export(
+    recording: &Path,
+    out: &Path,
+    kind: EdfKind,
+    version: Option<YLabVersion>,
+) -> io::Result<(usize, usize)> {
+    let meta = Meta::read(recording).ok();
+    let version = meta
+        .as_ref()
+        .and_then(|meta| meta.device.as_ref())
+        .and_then(|device| device.version.parse::<YLabVersion>().ok())
+        .or(version)
+        .ok_or(io::Error::other("YLab version unknown, please give one"))?;
+    let signals = signals(version);
+    if signals.is_empty() {
+        return Err(io::Error::other(format!("{} has no sampled banks", version)));
+    }
+    let span = Span::of(recording, &signals)?;
+    if !span.start.is_finite() {
+        return Err(io::Error::other("no samples in recording"));
+    }
+    let n_records = span.n_records(&signals);
+    let annotations = annotations(&read_marks(recording), span.start, n_records);
+    let mut file = BufWriter::new(File::create(out)?);
+    write_header(&mut file, kind, meta.as_ref(), version, &signals, &annotations)?;
+    let mut records = Records::new(kind, &signals, &annotations, span.first);
+    for_each_sample(recording, |sample| {
+        let time = sample.time.as_secs_f64();
+        for (n, signal) in signals.iter().enumerate() {
+            if signal.bank == sample.sensory {
+                let index = signal.index(time, span.start);
+                records.add(&mut file, n, index, sample.read[signal.chan])?;
+            }
+        }
+        Ok(())
+    })?;
+    records.finish(&mut file)?;
+    file.flush()?;
+    Ok((signals.len(), n_records))
+}
+
+/// Where a recording starts and ends, from a first reading
+struct Span {
+    start: f64,              // earliest time stamp, in seconds
+    end: Vec<f64>,           // latest time stamp of every signal
+    first: Vec<Option<f64>>, // earliest value of every signal
+}
+
+impl Span {
+    fn of(recording: &Path, signals: &[Signal]) -> io::Result<Span> {
+        let mut span = Span {
+            start: f64::INFINITY,
+            end: vec![f64::NEG_INFINITY; signals.len()],
+            first: vec![None; signals.len()],
+        };
+        let mut first_time = vec![f64::INFINITY; signals.len()];
+        for_each_sample(recording, |sample| {
+            let time = sample.time.as_secs_f64();
+            span.start = span.start.min(time);
+            for (n, signal) in signals.iter().enumerate() {
+                if signal.bank == sample.sensory {
+                    span.end[n] = span.end[n].max(time);
+                    if time < first_time[n] {
+                        first_time[n] = time;
+                        span.first[n] = Some(sample.read[signal.chan]);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        Ok(span)
+    }
+
+    /// Data records needed for the longest signal
+    fn n_records(&self, signals: &[Signal]) -> usize {
+        signals
+            .iter()
+            .zip(&self.end)
+            .filter(|(_, end)| end.is_finite())
+            .map(|(signal, end)| {
+                (signal.index(*end, self.start) + 1).div_ceil(signal.per_record())
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Path of an export, next to the recording
+pub fn export_path(recording: &Path, kind: EdfKind) -> PathBuf {
+    recording.with_file_name(format!("{}.{}", sinks::stem(recording), kind.extension()))
+}
+
+/// The signals of a YLab version, one per channel of its sampled banks
+fn signals(version: YLabVersion) -> Vec<Signal> {
+    let labels = version.bank_labels();
+    let mut signals = vec![];
+    for (bank, sensory) in version.sensories().into_iter().enumerate() {
+        let (Some(rate), Some(calibration)) = (sensory.hz(), sensory.calibration()) else {
+            continue;
+        };
+        for chan in 0..sensory.n_chan() as usize {
+            signals.push(Signal {
+                label: format!("{} {}", labels[bank], chan),
+                calibration,
+                rate,
+                bank: bank as u8,
+                chan,
+            });
+        }
+    }
+    signals
+}
+
+/// Annotations of every data record, as TALs
+///
+/// Every record starts with its time-keeping TAL,
+/// markers go into the record they fall into.
+fn annotations(marks: &[Mark], start: f64, n_records: usize) -> Vec<String> {
+    let mut records: Vec<String> = (0..n_records)
+        .map(|record| {
+            let onset = record * RECORD_SECS;
+            format!("+{}{}{}{}", onset, TAL_SEP, TAL_SEP, TAL_END)
+        })
+        .collect();
+    for mark in marks {
+        let onset = mark.time.as_secs_f64() - start;
+        let record = (onset.max(0.0) as usize / RECORD_SECS).min(n_records.saturating_sub(1));
+        // separators are not allowed in labels
+        let label: String = mark
+            .label
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        if let Some(tals) = records.get_mut(record) {
+            tals.push_str(&format!("{:+.4}{}{}{}{}", onset, TAL_SEP, label, TAL_SEP, TAL_END));
+        }
+    }
+    records
+}
+
+/// A header field, left aligned and padded with spaces
+fn field(text: &str, len: usize) -> String {
+    let ascii: String = text
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_control() { c } else { '_' })
+        .take(len)
+        .collect();
+    format!("{:<len$}", ascii)
+}
+
+/// A number for a header field of 8 characters
+///
+/// Decimals are dropped as far as needed to fit.
+fn number(value: f64) -> String {
+    let text = if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        let mut text = format!("{}", value);
+        let mut decimals = 7;
+        while text.len() > 8 && decimals > 0 {
+            text = format!("{:.*}", decimals, value);
+            decimals -= 1;
+        }
+        if text.len() > 8 {
+            text = format!("{:.0}", value);
+        }
+        text
+    };
+    field(&text, 8)
+}
+
+/// Start date and time for the header, from an RFC 3339 time stamp
+fn start_time(started: Option<&str>) -> (String, String, String) {
+    const MONTHS: [&str; 12] = [
+        "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+    ];
+    // e.g. 2024-01-31T12:34:56Z
+    let parts = started.and_then(|started| {
+        let year = started.get(0..4)?;
+        let month = started.get(5..7)?.parse::<usize>().ok()?;
+        let day = started.get(8..10)?;
+        let time = started.get(11..19)?;
+        Some((year, month, day, time))
+    });
+    match parts {
+        Some((year, month, day, time)) if (1..=12).contains(&month) => (
+            format!("{}.{:02}.{}", day, month, &year[2..]),
+            time.replace(':', "."),
+            format!("{}-{}-{}", day, MONTHS[month - 1], year),
+        ),
+        // EDF+ for unknown dates
+        _ => ("01.01.85".into(), "00.00.00".into(), "X".into()),
+    }
+}
+
+fn write_header(
+    file: &mut impl Write,
+    kind: EdfKind,
+    meta: Option<&Meta>,
+    version: YLabVersion,
+    signals: &[Signal],
+    annotations: &[String],
+) -> io::Result<()> {
+    let n_signals = signals.len() + 1; // with annotations
+    let none = |text: &str| {
+        if text.trim().is_empty() {
+            "X".to_string()
+        } else {
+            text.trim().replace(' ', "_")
+        }
+    };
+    let (participant, session) = match meta {
+        Some(meta) => (none(&meta.participant), none(&meta.session)),
+        None => ("X".to_string(), "X".to_string()),
+    };
+    let (date, time, start_date) = start_time(meta.and_then(|meta| meta.started.as_deref()));
+    let mut header = String::new();
+    // version, patient and recording identification
+    match kind {
+        EdfKind::Edf => header.push_str(&field("0", 8)),
+        EdfKind::Bdf => header.push_str(&field(" BIOSEMI", 8)), // first byte replaced below
+    }
+    header.push_str(&field(&format!("{} X X X", participant), 80));
+    header.push_str(&field(
+        &format!("Startdate {} {} X Ystudio_YLab_{}", start_date, session, none(&version.to_string())),
+        80,
+    ));
+    header.push_str(&field(&date, 8));
+    header.push_str(&field(&time, 8));
+    header.push_str(&field(&(256 * (n_signals + 1)).to_string(), 8));
+    header.push_str(&field(
+        match kind {
+            EdfKind::Edf => "EDF+C",
+            EdfKind::Bdf => "BDF+C",
+        },
+        44,
+    ));
+    header.push_str(&field(&annotations.len().to_string(), 8));
+    header.push_str(&field(&RECORD_SECS.to_string(), 8));
+    header.push_str(&field(&n_signals.to_string(), 4));
+
+    // signal fields, one field for all signals at a time
+    let annotation_label = format!("{} Annotations", kind);
+    let (digital_min, digital_max) = kind.range();
+    let annotation_samples = annotation_samples(kind, annotations);
+    for signal in signals {
+        header.push_str(&field(&signal.label, 16));
+    }
+    header.push_str(&field(&annotation_label, 16));
+    for _ in signals {
+        header.push_str(&field("YLab", 80));
+    }
+    header.push_str(&field("", 80));
+    for signal in signals {
+        header.push_str(&field(signal.calibration.unit, 8));
+    }
+    header.push_str(&field("", 8));
+    // the full digital range, raw readings may be negative (e.g. simulated)
+    for signal in signals {
+        header.push_str(&number(signal.calibration.to_physical(digital_min as f64)));
+    }
+    header.push_str(&number(-1.0));
+    for signal in signals {
+        header.push_str(&number(signal.calibration.to_physical(digital_max as f64)));
+    }
+    header.push_str(&number(1.0));
+    for _ in 0..n_signals {
+        header.push_str(&number(digital_min as f64));
+    }
+    for _ in 0..n_signals {
+        header.push_str(&number(digital_max as f64));
+    }
+    for _ in signals {
+        header.push_str(&field("", 80));
+    }
+    header.push_str(&field("", 80));
+    for signal in signals {
+        header.push_str(&field(&(signal.rate * RECORD_SECS).to_string(), 8));
+    }
+    header.push_str(&field(&annotation_samples.to_string(), 8));
+    for _ in 0..n_signals {
+        header.push_str(&field("", 32));
+    }
+
+    let mut bytes = header.into_bytes();
+    if kind == EdfKind::Bdf {
+        bytes[0] = 0xFF;
+    }
+    file.write_all(&bytes)
+}
+
+/// Samples per record of the annotation signal, enough for the longest record
+fn annotation_samples(kind: EdfKind, annotations: &[String]) -> usize {
+    let longest = annotations.iter().map(String::len).max().unwrap_or(0);
+    longest.div_ceil(kind.width()).max(1)
+}
+
+/// Data records being filled, written in order
+///
+/// Samples are placed into their record as they come. A record is written
+/// once samples have moved `PENDING_RECORDS` records past it,
+/// samples for records already written are dropped.
+struct Records<'a> {
+    kind: EdfKind,
+    signals: &'a [Signal],
+    annotations: &'a [String],
+    annotation_bytes: usize,
+    written: usize,
+    pending: VecDeque<Vec<Vec<Option<f64>>>>, // by record, signal and index in record
+    last: Vec<Option<f64>>,                   // of every signal, for filling lost samples
+}
+
+impl<'a> Records<'a> {
+    fn new(
+        kind: EdfKind,
+        signals: &'a [Signal],
+        annotations: &'a [String],
+        first: Vec<Option<f64>>,
+    ) -> Self {
+        Records {
+            kind,
+            signals,
+            annotations,
+            annotation_bytes: annotation_samples(kind, annotations) * kind.width(),
+            written: 0,
+            pending: VecDeque::new(),
+            last: first,
+        }
+    }
+
+    fn empty_record(&self) -> Vec<Vec<Option<f64>>> {
+        self.signals
+            .iter()
+            .map(|signal| vec![None; signal.per_record()])
+            .collect()
+    }
+
+    /// Placing a value of a signal, writing the records it has moved past
+    fn add(
+        &mut self,
+        file: &mut impl Write,
+        signal: usize,
+        index: usize,
+        value: f64,
+    ) -> io::Result<()> {
+        let per_record = self.signals[signal].per_record();
+        let record = index / per_record;
+        if record < self.written || record >= self.annotations.len() {
+            return Ok(());
+        }
+        while self.written + self.pending.len() <= record {
+            let empty = self.empty_record();
+            self.pending.push_back(empty);
+        }
+        self.pending[record - self.written][signal][index % per_record] = Some(value);
+        while self.pending.len() > PENDING_RECORDS {
+            self.write_next(file)?;
+        }
+        Ok(())
+    }
+
+    /// Writing the remaining records
+    fn finish(mut self, file: &mut impl Write) -> io::Result<()> {
+        while self.written < self.annotations.len() {
+            self.write_next(file)?;
+        }
+        Ok(())
+    }
+
+    fn write_next(&mut self, file: &mut impl Write) -> io::Result<()> {
+        let values = self.pending.pop_front().unwrap_or_else(|| self.empty_record());
+        let width = self.kind.width();
+        let (d0, d1) = self.kind.range();
+        let mut bytes = Vec::new();
+        for (values, last) in values.iter().zip(self.last.iter_mut()) {
+            for value in values {
+                if value.is_some() {
+                    *last = *value;
+                }
+                let digital = last.unwrap_or(0.0).round().clamp(d0 as f64, d1 as f64) as i32;
+                bytes.extend_from_slice(&digital.to_le_bytes()[..width]);
+            }
+        }
+        let mut tals = self.annotations[self.written].clone().into_bytes();
+        tals.resize(self.annotation_bytes, 0);
+        bytes.extend(tals);
+        self.written += 1;
+        file.write_all(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marks::marks_path;
+    use crate::ylab::data::Ytf8;
+    use std::time::Duration;
+
+    /// A Go recording of `secs` seconds, 500 Hz on the ADC bank
+    fn recording(name: &str, secs: f64, value: impl Fn(usize) -> f64) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ystudio-edf-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recording.ytf");
+        let mut file = File::create(&path).unwrap();
+        for index in 0..(secs * 500.0) as usize {
+            let sample = Ytf8 {
+                dev: 0,
+                sensory: 1,
+                time: Duration::from_millis(1000 + 2 * index as u64),
+                read: [value(index), 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            };
+            writeln!(file, "{}", sample.to_csv_line()).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn header_fields_have_their_widths() {
+        assert_eq!(field("YLab", 8), "YLab    ");
+        assert_eq!(field("Ystudio_YLab_Go", 8), "Ystudio_");
+        assert_eq!(field("µV", 8), "_V      ");
+        for value in [0.0, -1.0, 3300.0, -32768.0, 0.123456789, -26406.447, -6760303.516] {
+            assert_eq!(number(value).len(), 8, "{}", value);
+        }
+        assert_eq!(number(-26406.447).trim(), "-26406.4");
+
+        let signals = signals(YLabVersion::Go);
+        assert_eq!(signals.len(), 3);
+        let mut header = vec![];
+        write_header(&mut header, EdfKind::Edf, None, YLabVersion::Go, &signals, &[]).unwrap();
+        let n_signals = signals.len() + 1;
+        assert_eq!(header.len(), 256 * (n_signals + 1));
+        let text = String::from_utf8(header).unwrap();
+        assert_eq!(&text[0..8], "0       ");
+        assert_eq!(&text[184..192], "1280    ");
+        assert_eq!(&text[252..256], "4   ");
+        assert_eq!(&text[256..272], "ADC 0           ");
+    }
+
+    #[test]
+    fn records_hold_every_signal_and_annotations() {
+        let path = recording("records", 2.5, |index| (index % 100) as f64);
+        let out = path.with_extension("edf");
+        let (n_signals, n_records) =
+            export(&path, &out, EdfKind::Edf, Some(YLabVersion::Go)).unwrap();
+        assert_eq!((n_signals, n_records), (3, 3));
+        let bytes = std::fs::read(&out).unwrap();
+        let header = 256 * (n_signals + 2);
+        // 3 channels of 500 samples, 2 bytes each, plus one annotation sample
+        let record = 3 * 500 * 2 + 2 * annotation_samples(EdfKind::Edf, &annotations(&[], 0.0, 3));
+        assert_eq!(bytes.len(), header + n_records * record);
+        let first: Vec<i16> = bytes[header..header + 6]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(first, [0, 1, 2]);
+        // the last record is filled up with the last value
+        let last = header + 2 * record + 2 * 499;
+        assert_eq!(i16::from_le_bytes([bytes[last], bytes[last + 1]]), 49);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn negative_values_are_kept() {
+        let path = recording("negative", 1.0, |index| -(index as f64));
+        let out = path.with_extension("bdf");
+        export(&path, &out, EdfKind::Bdf, Some(YLabVersion::Go)).unwrap();
+        let bytes = std::fs::read(&out).unwrap();
+        let header = 256 * 5;
+        let value = |index: usize| {
+            let at = header + 3 * index;
+            i32::from_le_bytes([0, bytes[at], bytes[at + 1], bytes[at + 2]]) >> 8
+        };
+        assert_eq!((value(0), value(1), value(499)), (0, -1, -499));
+        // physical minimum of the first signal
+        let text = String::from_utf8_lossy(&bytes[..header]);
+        let physical_min = 256 + 4 * (16 + 80 + 8);
+        assert!(text[physical_min..physical_min + 8].trim().parse::<f64>().unwrap() < 0.0);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn annotations_are_tals() {
+        let marks = [
+            Mark {
+                time: Duration::from_millis(1500),
+                label: "eyes\tclosed".into(),
+            },
+            Mark {
+                time: Duration::from_millis(9000),
+                label: "end".into(),
+            },
+        ];
+        let tals = annotations(&marks, 1.0, 2);
+        assert_eq!(tals[0], "+0\u{14}\u{14}\u{0}+0.5000\u{14}eyes closed\u{14}\u{0}");
+        // markers after the end go into the last record
+        assert_eq!(tals[1], "+1\u{14}\u{14}\u{0}+8.0000\u{14}end\u{14}\u{0}");
+
+        let path = recording("marks", 2.0, |_| 0.0);
+        std::fs::write(marks_path(&path), marks[0].to_csv_line() + "\n").unwrap();
+        let out = path.with_extension("edf");
+        export(&path, &out, EdfKind::Edf, Some(YLabVersion::Go)).unwrap();
+        let bytes = std::fs::read(&out).unwrap();
+        let tal = b"+0\x14\x14\x00+0.5000\x14eyes closed\x14\x00";
+        assert!(bytes.windows(tal.len()).any(|window| window == tal));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
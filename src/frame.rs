@@ -0,0 +1,284 @@
+/// Binary YLab transport format (Ytf frames)
+///
+/// A compact alternative to Ytf8 CSV lines, for high-throughput
+/// YLabs. A frame has a fixed length of 42 bytes:
+///
+/// | bytes | content                                  |
+/// |-------|------------------------------------------|
+/// | 2     | sync word 0xA5 0x5A                       |
+/// | 1     | sensory                                   |
+/// | 1     | device                                    |
+/// | 4     | device time in ms (u32, little endian)    |
+/// | 32    | 8 readings (f32, little endian)           |
+/// | 2     | CRC-16/CCITT of all bytes before (LE)     |
+///
+/// The decoder takes any mix of frames and CSV lines.
+/// CSV is plain ASCII, so it never contains the first sync byte.
+/// After corrupted bytes the decoder skips ahead to the next sync word.
+use crate::ylab::data::Ytf8;
+use std::time::Duration;
+
+pub const SYNC: [u8; 2] = [0xA5, 0x5A];
+pub const FRAME_LEN: usize = 42;
+
+/// Longest CSV line accepted, longer runs without newline are dropped
+const MAX_LINE: usize = 512;
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF)
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Encoding a sample as binary frame
+///
+/// Readings are stored as f32 and the time as u32 ms,
+/// which wraps after 49 days.
+pub fn encode(sample: &Ytf8) -> [u8; FRAME_LEN] {
+    let mut frame = [0u8; FRAME_LEN];
+    frame[0..2].copy_from_slice(&SYNC);
+    frame[2] = sample.sensory;
+    frame[3] = sample.dev;
+    frame[4..8].copy_from_slice(&(sample.time.as_millis() as u32).to_le_bytes());
+    for (chan, value) in sample.read.iter().enumerate() {
+        let at = 8 + 4 * chan;
+        frame[at..at + 4].copy_from_slice(&(*value as f32).to_le_bytes());
+    }
+    let crc = crc16(&frame[..FRAME_LEN - 2]);
+    frame[FRAME_LEN - 2..].copy_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+/// Decoding a complete frame, None if the sync word or CRC do not match
+pub fn decode(frame: &[u8]) -> Option<Ytf8> {
+    if frame.len() < FRAME_LEN || frame[0..2] != SYNC {
+        return None;
+    }
+    let crc = u16::from_le_bytes([frame[FRAME_LEN - 2], frame[FRAME_LEN - 1]]);
+    if crc16(&frame[..FRAME_LEN - 2]) != crc {
+        return None;
+    }
+    let millis = u32::from_le_bytes(frame[4..8].try_into().unwrap());
+    let mut read = [0.0; 8];
+    for (chan, value) in read.iter_mut().enumerate() {
+        let at = 8 + 4 * chan;
+        *value = f32::from_le_bytes(frame[at..at + 4].try_into().unwrap()) as f64;
+    }
+    Some(Ytf8 {
+        sensory: frame[2],
+        dev: frame[3],
+        time: Duration::from_millis(millis as u64),
+        read,
+    })
+}
+
+/// What the decoder found in the stream
+#[derive(Debug, Clone)]
+pub enum Decoded {
+    /// a binary frame with valid CRC
+    Frame(Ytf8),
+    /// a text line (without line ending), to be parsed as CSV
+    Line(String),
+    /// bytes that were dropped to get back in sync
+    Skipped(usize),
+}
+
+/// Decoder for a byte stream of frames and CSV lines
+#[derive(Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adding bytes as they arrive
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The next frame or line, None if more bytes are needed
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Decoded> {
+        loop {
+            if self.buf.is_empty() {
+                return None;
+            }
+            // a frame starts here
+            if self.buf[0] == SYNC[0] {
+                if self.buf.len() < 2 || (self.buf[1] == SYNC[1] && self.buf.len() < FRAME_LEN) {
+                    return None;
+                }
+                if let Some(sample) = decode(&self.buf) {
+                    self.buf.drain(..FRAME_LEN);
+                    return Some(Decoded::Frame(sample));
+                }
+                // broken frame or stray sync byte: resync after it
+                return Some(Decoded::Skipped(self.skip(1)));
+            }
+            // otherwise a text line, up to the newline or the next frame
+            let newline = self.buf.iter().position(|b| *b == b'\n');
+            let sync = self.buf.iter().position(|b| *b == SYNC[0]);
+            match (newline, sync) {
+                // a frame interrupts the line
+                (Some(end), Some(start)) if start < end => {
+                    return Some(Decoded::Skipped(self.skip(start)))
+                }
+                (Some(end), _) => {
+                    let line: Vec<u8> = self.buf.drain(..=end).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end_matches(['\r', '\n']);
+                    if line.is_empty() {
+                        continue;
+                    }
+                    return Some(Decoded::Line(line.to_string()));
+                }
+                (None, Some(start)) => return Some(Decoded::Skipped(self.skip(start))),
+                // runaway line
+                (None, None) if self.buf.len() > MAX_LINE => {
+                    return Some(Decoded::Skipped(self.skip(self.buf.len())))
+                }
+                // line not yet complete
+                (None, None) => return None,
+            }
+        }
+    }
+
+    /// Remaining bytes at the end of the stream, as last line
+    pub fn finish(&mut self) -> Option<Decoded> {
+        if self.buf.is_empty() || self.buf[0] == SYNC[0] {
+            let n = self.buf.len();
+            self.buf.clear();
+            return (n > 0).then_some(Decoded::Skipped(n));
+        }
+        self.buf.push(b'\n');
+        self.next()
+    }
+
+    fn skip(&mut self, n: usize) -> usize {
+        self.buf.drain(..n);
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Ytf8 {
+        Ytf8 {
+            dev: 2,
+            sensory: 1,
+            time: Duration::from_millis(123_456),
+            read: [0.5, -1.0, 2.25, 0.0, 4095.0, 1e-3, -7.5, 100.0],
+        }
+    }
+
+    fn assert_same(decoded: &Ytf8, sent: &Ytf8) {
+        assert_eq!(decoded.dev, sent.dev);
+        assert_eq!(decoded.sensory, sent.sensory);
+        assert_eq!(decoded.time, sent.time);
+        for (got, want) in decoded.read.iter().zip(sent.read.iter()) {
+            assert_eq!(*got, *want as f32 as f64);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let sent = sample();
+        let frame = encode(&sent);
+        assert_eq!(frame.len(), FRAME_LEN);
+        assert_eq!(frame[0..2], SYNC);
+        assert_same(&decode(&frame).unwrap(), &sent);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&frame);
+        match decoder.next() {
+            Some(Decoded::Frame(decoded)) => assert_same(&decoded, &sent),
+            other => panic!("expected a frame, got {:?}", other),
+        }
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn corrupted_crc_is_skipped() {
+        let mut frame = encode(&sample());
+        frame[10] ^= 0xFF;
+        assert!(decode(&frame).is_none());
+
+        // the decoder drops the broken frame and finds the next one
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&frame);
+        decoder.push(&encode(&sample()));
+        let mut skipped = 0;
+        loop {
+            match decoder.next() {
+                Some(Decoded::Skipped(n)) => skipped += n,
+                Some(Decoded::Frame(decoded)) => {
+                    assert_same(&decoded, &sample());
+                    break;
+                }
+                other => panic!("expected the second frame, got {:?}", other),
+            }
+        }
+        assert_eq!(skipped, FRAME_LEN);
+    }
+
+    #[test]
+    fn garbage_before_sync() {
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&[0x00, 0xA5, 0x13, 0xFF]);
+        decoder.push(&encode(&sample()));
+        let mut skipped = 0;
+        let decoded = loop {
+            match decoder.next() {
+                Some(Decoded::Skipped(n)) => skipped += n,
+                Some(Decoded::Frame(decoded)) => break decoded,
+                other => panic!("expected a frame, got {:?}", other),
+            }
+        };
+        assert_eq!(skipped, 4);
+        assert_same(&decoded, &sample());
+    }
+
+    #[test]
+    fn frame_split_across_reads() {
+        let frame = encode(&sample());
+        let mut decoder = FrameDecoder::new();
+        for part in frame.chunks(5) {
+            assert!(decoder.next().is_none());
+            decoder.push(part);
+        }
+        match decoder.next() {
+            Some(Decoded::Frame(decoded)) => assert_same(&decoded, &sample()),
+            other => panic!("expected a frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lines_between_frames() {
+        let mut decoder = FrameDecoder::new();
+        decoder.push(b"1000,0,1,2,3,4,5,6,7,8\r\n");
+        decoder.push(&encode(&sample()));
+        decoder.push(b"1001,0,1");
+        assert!(
+            matches!(decoder.next(), Some(Decoded::Line(line)) if line == "1000,0,1,2,3,4,5,6,7,8")
+        );
+        assert!(matches!(decoder.next(), Some(Decoded::Frame(_))));
+        // the last line is incomplete until the stream ends
+        assert!(decoder.next().is_none());
+        assert!(matches!(decoder.finish(), Some(Decoded::Line(line)) if line == "1001,0,1"));
+    }
+}
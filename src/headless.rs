@@ -0,0 +1,216 @@
+/// Headless recording
+///
+/// drives the YLab and Yldest threads over their command channels,
+/// just like the GUI does, but without starting eframe.
+/// Used for long sessions on machines without a display.
+use crate::yui::*;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use ystudio::detect::max_detect_time;
+use ystudio::meta::Meta;
+use ystudio::segments::{self, Manifest, Rotation};
+use ystudio::sinks::{Compression, Format};
+
+/// How long to wait for a state transition of YLab or Yldest
+const TRANSITION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Waiting until a shared state fulfills a condition
+///
+/// returns false on timeout
+fn wait_for<S>(state: &Arc<Mutex<S>>, cond: impl Fn(&S) -> bool) -> bool {
+    wait_within(state, TRANSITION_TIMEOUT, cond)
+}
+
+/// Waiting as `wait_for`, with a timeout of its own
+fn wait_within<S>(state: &Arc<Mutex<S>>, timeout: Duration, cond: impl Fn(&S) -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if cond(&state.lock().unwrap()) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+/// Record from a YLab into a Yld (or wide CSV, Parquet) file
+///
+/// + connects to the YLab on the given port, detecting the version if not given
+/// + records into `out` for the given duration, or until Enter is pressed,
+///   with `meta` (participant, session, notes) in the sidecar,
+///   split into segments by `rotation`
+/// + stops recording and disconnects
+///
+/// Returns the number of Yld rows written, one per channel of a sample.
+pub fn record(
+    ystud: &Ystudio,
+    port: String,
+    version: Option<YLabVersion>,
+    out: PathBuf,
+    duration: Option<Duration>,
+    rotation: Rotation,
+    meta: Meta,
+) -> Result<usize, String> {
+    // The YLab thread only accepts Connect after it has listed the ports
+    if !wait_for(&ystud.ylab_state, |s| {
+        matches!(s, YLabState::Disconnected { ports: Some(_) })
+    }) {
+        return Err("YLab thread did not list serial ports".to_string());
+    }
+    ystud
+        .ylab_cmd
+        .send(match version {
+            Some(version) => YLabCmd::Connect {
+                version,
+                port_name: port.clone(),
+            },
+            None => YLabCmd::Detect {
+                port_name: port.clone(),
+            },
+        })
+        .unwrap();
+    // detection tries every baud rate
+    let timeout = match version {
+        Some(_) => TRANSITION_TIMEOUT,
+        None => TRANSITION_TIMEOUT + max_detect_time(),
+    };
+    if !wait_within(&ystud.ylab_state, timeout, |s| {
+        matches!(s, YLabState::Reading { .. } | YLabState::Failed { .. })
+    }) {
+        return Err(format!("could not connect to {}", port));
+    }
+    // the version may have been detected, the port found by serial number
+    let (version, port_name) = match ystud.ylab_state.lock().unwrap().clone() {
+        YLabState::Reading { version, port_name } => (version, port_name),
+        YLabState::Failed { reason, .. } => return Err(reason),
+        _ => return Err(format!("could not connect to {}", port)),
+    };
+    println!("Reading from {} {}", version, port_name);
+    let meta = Meta {
+        participant: meta.participant,
+        session: meta.session,
+        notes: meta.notes,
+        ..Meta::new(version, &port_name)
+    };
+
+    // splitting the output path for Yldest
+    let dir = match out.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::env::current_dir().map_err(|e| e.to_string())?,
+    };
+    let file_name = out
+        .file_name()
+        .map(PathBuf::from)
+        .ok_or(format!("not a file name: {:?}", out))?;
+    ystud
+        .yldest_cmd
+        .send(YldestCmd::New {
+            change_dir: Some(dir),
+            file_name: Some(file_name),
+            format: Format::from_path(&out),
+            compression: Compression::from_path(&out),
+            rotation,
+            meta: Box::new(meta),
+        })
+        .unwrap();
+    if !wait_for(&ystud.yldest_state, |s| {
+        matches!(
+            s,
+            YldestState::Recording { .. } | YldestState::Failed { .. }
+        )
+    }) {
+        return Err(format!("could not start recording to {:?}", out));
+    }
+    let path = match ystud.yldest_state.lock().unwrap().clone() {
+        YldestState::Recording { path } => path,
+        YldestState::Failed { reason, .. } => return Err(reason),
+        _ => out.clone(),
+    };
+    let started = Instant::now();
+
+    // Waiting for the end of the session
+    match duration {
+        Some(duration) => println!(
+            "Recording to {:?} for {}",
+            path,
+            humantime::format_duration(duration)
+        ),
+        None => println!("Recording to {:?}, press Enter to stop", path),
+    }
+    let (enter_send, enter_rec) = mpsc::channel();
+    if duration.is_none() {
+        thread::spawn(move || {
+            let mut line = String::new();
+            let _ = std::io::stdin().lock().read_line(&mut line);
+            let _ = enter_send.send(());
+        });
+    }
+    // why recording failed, e.g. on a full disk
+    let mut failure = None;
+    loop {
+        if duration.is_some_and(|d| started.elapsed() >= d) || enter_rec.try_recv().is_ok() {
+            break;
+        }
+        // no use going on when the YLab or the disk has failed
+        if let YLabState::Failed { reason, .. } = ystud.ylab_state.lock().unwrap().clone() {
+            eprintln!("Stopping early: {}", reason);
+            break;
+        }
+        if let YldestState::Failed { reason, .. } = ystud.yldest_state.lock().unwrap().clone() {
+            failure = Some(reason);
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    // Disconnecting first, so that Stop writes out everything that was read
+    ystud.ylab_cmd.send(YLabCmd::Disconnect).unwrap();
+    wait_for(&ystud.ylab_state, |s| {
+        matches!(s, YLabState::Disconnected { .. })
+    });
+    // Stop would turn the failure into Idle
+    if let Some(reason) = failure {
+        return Err(reason);
+    }
+    ystud.yldest_cmd.send(YldestCmd::Stop).unwrap();
+    if !wait_for(&ystud.yldest_state, |s| {
+        matches!(s, YldestState::Idle { .. } | YldestState::Failed { .. })
+    }) {
+        return Err("recording did not stop".to_string());
+    }
+    // the recording may have failed on the way, e.g. on a full disk
+    if let YldestState::Failed { reason, .. } = ystud.yldest_state.lock().unwrap().clone() {
+        return Err(reason);
+    }
+
+    // Summary, from the metadata of the recording
+    if segments::is_manifest(&path) {
+        let manifest = Manifest::read(&path).map_err(|e| e.to_string())?;
+        println!("{} segments", manifest.segments.len());
+    }
+    let meta = Meta::read(&path).map_err(|e| e.to_string())?;
+    println!(
+        "Recorded {} rows in {} to {:?}",
+        meta.rows,
+        humantime::format_duration(Duration::from_secs(started.elapsed().as_secs())),
+        path
+    );
+    let labels = version.bank_labels();
+    for (bank, counts) in meta.quality.banks.iter().enumerate() {
+        if counts.issues() > 0 {
+            println!(
+                "{}: {} gaps ({} samples lost), {} duplicates, {} out of order",
+                labels.get(bank).copied().unwrap_or("?"),
+                counts.gaps,
+                counts.missing,
+                counts.duplicates,
+                counts.out_of_order
+            );
+        }
+    }
+    if meta.quality.parse_errors > 0 {
+        println!("{} unreadable lines", meta.quality.parse_errors);
+    }
+    Ok(meta.rows as usize)
+}
@@ -0,0 +1,56 @@
+//! Ystudio library
+//!
+//! The parts of Ystudio that do not depend on the GUI,
+//! for use in analysis tools and other Rust programs:
+//!
+//! + `ylab`: the YLab device model, data formats (`Ytf8`, `Yld`) and the reading thread
+//! + `frame`: the binary transport format (Ytf frames) with its decoder
+//! + `clock`: mapping device clocks onto the Ystudio clock
+//! + `sources`: data sources the reading thread can connect to
+//! + `yldest`: the recording thread, storing Yld files
+//! + `sinks`: file formats of recordings (Yld, wide CSV, Parquet), compression and conversion
+//! + `segments`: long recordings split into segments, with a manifest
+//! + `edf`: exporting recordings to EDF+/BDF+ for biosignal tools
+//! + `xdf`: exporting recordings to XDF, the file format of Lab Streaming Layer
+//! + `meta` and `quality`: recording metadata and data quality counters
+//! + `marks`: event markers, on the same clock as the data
+//! + `recovery`: repairing recordings interrupted by a crash
+//! + `triggers`: markers from stimulus software over localhost UDP/TCP
+//! + `stream`: publishing live samples to other programs over localhost TCP
+//! + `control`: remote control of reading and recording over localhost TCP
+//! + `yldfile`: reading recorded Yld and Ytf files
+//! + `replay` and `ysim`: replayed recordings and the simulator
+//! + `dsp`: low-pass filters and spectra
+//! + `builds`: firmware builds of YLab
+//! + `detect`: telling the YLab version from its traffic
+//!
+//! The Ystudio app (`ystudio-zero`) is a thin egui front end on top.
+
+pub mod builds;
+pub mod clock;
+pub mod control;
+pub mod detect;
+pub mod dsp;
+pub mod edf;
+pub mod frame;
+pub mod marks;
+pub mod meta;
+pub mod quality;
+pub mod recovery;
+pub mod replay;
+pub mod segments;
+pub mod sinks;
+pub mod sources;
+pub mod stream;
+pub mod triggers;
+pub mod xdf;
+pub mod ylab;
+pub mod yldest;
+pub mod yldfile;
+pub mod ysim;
+
+pub use sources::{DataSource, Reading};
+pub use ylab::data::{Banks, Ytf8, Yld};
+pub use ylab::{ylab_thread, Calibration, Sensory, YLabCmd, YLabState, YLabVersion};
+pub use yldest::{yldest_thread, YldestCmd, YldestState};
+pub use yldfile::YldFile;
@@ -1,16 +1,24 @@
-#![windows_subsystem = "windows"]
-//mod yui;
-mod yldest;
-mod ylab;
-mod ystudio;
-mod builds;
-
-//use builds::BUILDS;
-use ystudio::*;
-use ylab::*;
-use yldest::yldest_thread;
+// no console window for the GUI on Windows, see `attach_console`
+#![cfg_attr(all(windows, not(debug_assertions)), windows_subsystem = "windows")]
+mod yui;
+mod headless;
+
+//use ystudio::builds::BUILDS;
+use yui::*;
+use ystudio::yldest::yldest_thread;
+use ystudio::triggers::{listen_triggers, TRIGGER_PORT};
+use ystudio::stream::{serve_stream, Broadcast, STREAM_PORT};
+use ystudio::control::{serve_control, Controls, CONTROL_PORT};
+use ystudio::recovery;
+use ystudio::segments::Rotation;
+use ystudio::sinks::{self, Compression, Format};
+use ystudio::edf::{self, EdfKind};
+use ystudio::xdf;
 pub use std::sync::mpsc::{channel, Sender, Receiver};
 use std::sync::{Arc, Mutex};
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[allow(unused_imports)]
 use log::{info, warn, debug, error};
@@ -27,9 +35,197 @@ use log::{info, warn, debug, error};
 const YLD_WIND_LEN:usize = 20_000;
 const YTF_WIND_LEN:usize = 1_000_000;
 
+/// Command line
+///
+/// Without a command, the GUI is started.
+#[derive(Parser)]
+#[command(version, about = "Capture and view data from YLab devices")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// localhost port (UDP and TCP) for markers from stimulus software, 0 to switch off
+    #[arg(long, global = true, default_value_t = TRIGGER_PORT)]
+    trigger_port: u16,
+    /// localhost port (TCP) for streaming live data to other programs, 0 to switch off
+    #[arg(long, global = true, default_value_t = STREAM_PORT)]
+    stream_port: u16,
+    /// localhost port (TCP) for remote control by experiment runners, 0 to switch off
+    #[arg(long, global = true, default_value_t = CONTROL_PORT)]
+    control_port: u16,
+}
+
+/// Formats for exporting recordings
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Edf,
+    Bdf,
+    Xdf,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Record from a YLab without starting the GUI
+    Record {
+        /// serial port of the YLab, e.g. /dev/ttyACM0 or COM3, or its USB serial number
+        #[arg(long)]
+        port: String,
+        /// YLab version, e.g. go, pro, mini, go-stress, go-motion-4; without, detected from the data
+        #[arg(long)]
+        version: Option<YLabVersion>,
+        /// file to record to, by extension: .yld, .csv (wide) or .parquet,
+        /// compressed with .gz or .zst (e.g. p01.yld.zst)
+        #[arg(long)]
+        out: PathBuf,
+        /// recording time, e.g. 90s, 30min or 2h; without, record until Enter is pressed
+        #[arg(long, value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+        /// start a new segment file after this many MB, listed in a manifest
+        #[arg(long)]
+        segment_mb: Option<u64>,
+        /// start a new segment file after this much recording time, e.g. 1h
+        #[arg(long, value_parser = humantime::parse_duration)]
+        segment_duration: Option<Duration>,
+        /// participant, stored in the metadata of the recording
+        #[arg(long, default_value = "")]
+        participant: String,
+        /// session, stored in the metadata of the recording
+        #[arg(long, default_value = "")]
+        session: String,
+        /// notes, stored in the metadata of the recording
+        #[arg(long, default_value = "")]
+        notes: String,
+    },
+    /// Convert a recording into another format (Yld, wide CSV or Parquet)
+    Convert {
+        /// recording to convert
+        input: PathBuf,
+        /// converted file, by default next to the input
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// format to convert to: yld, wide (csv) or parquet
+        #[arg(long, default_value = "parquet")]
+        format: Format,
+        /// compression of Yld and wide CSV: none, gzip or zstd,
+        /// by default as the extension of --out
+        #[arg(long)]
+        compression: Option<Compression>,
+    },
+    /// Export a recording (Yld or Parquet) to EDF+, BDF+ or XDF, with markers
+    Export {
+        /// recording to export
+        input: PathBuf,
+        /// exported file, by default next to the input
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// edf (16 bit), bdf (24 bit) or xdf (Lab Streaming Layer)
+        #[arg(long, value_enum, default_value_t = ExportFormat::Edf)]
+        format: ExportFormat,
+        /// YLab version, for recordings without metadata
+        #[arg(long)]
+        version: Option<YLabVersion>,
+    },
+}
+
+/// Printing into the console Ystudio was started from, if any
+///
+/// Release builds on Windows are GUI programs without a console,
+/// so subcommands (and --help) would print nowhere.
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // fails without a parent console, e.g. when started from the Explorer
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(all(windows, not(debug_assertions))))]
+fn attach_console() {}
+
 fn main() {
+    attach_console();
+    let cli = Cli::parse();
+    // converting and exporting need no device or threads
+    match &cli.command {
+        Some(Command::Convert { input, out, format, compression }) => {
+            let compression = compression
+                .or(out.as_deref().map(Compression::from_path))
+                .unwrap_or_default();
+            let out = out.clone().unwrap_or(sinks::converted_path(input, *format, compression));
+            match sinks::convert(input, &out, *format, compression) {
+                Ok((rows, skipped)) => {
+                    println!("Converted {} rows to {:?}", rows, out);
+                    if skipped > 0 {
+                        println!("{} unreadable lines skipped", skipped);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Converting {:?} failed: {}", input, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(Command::Export { input, out, format, version }) => {
+            let exported = match format {
+                ExportFormat::Xdf => {
+                    let out = out.clone().unwrap_or(xdf::export_path(input));
+                    xdf::export(input, &out, *version).map(|(streams, on_lsl)| {
+                        let clock = if on_lsl {
+                            ""
+                        } else {
+                            " (on the Ystudio clock, LSL time is only noted on Linux)"
+                        };
+                        format!("{} streams to {:?}{}", streams, out, clock)
+                    })
+                }
+                ExportFormat::Edf | ExportFormat::Bdf => {
+                    let kind = match format {
+                        ExportFormat::Bdf => EdfKind::Bdf,
+                        _ => EdfKind::Edf,
+                    };
+                    let out = out.clone().unwrap_or(edf::export_path(input, kind));
+                    edf::export(input, &out, kind, *version).map(|(signals, records)| {
+                        format!("{} signals, {} s to {:?}", signals, records, out)
+                    })
+                }
+            };
+            match exported {
+                Ok(exported) => println!("Exported {}", exported),
+                Err(e) => {
+                    eprintln!("Exporting {:?} failed: {}", input, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => {}
+    }
+    // starting the clock shared by data and markers
+    ystudio::clock::ystudio_time();
     println!("Hello Ystudio");
     eprintln!("and YLab");
+    // repairing recordings that were interrupted last time
+    for dir in recovery::recording_dirs() {
+        for (recording, recovered) in recovery::recover_dir(&dir) {
+            match recovered {
+                Ok(recovered) => {
+                    println!(
+                        "Recovered {:?}: {} rows, {} files repaired, {} bytes cut",
+                        recording, recovered.rows, recovered.files, recovered.cut
+                    );
+                    if let Some(aside) = recovered.set_aside {
+                        println!("Unreadable last segment set aside as {:?}", aside);
+                    }
+                }
+                Err(e) => eprintln!("Could not recover {:?}: {}", recording, e),
+            }
+        }
+    }
     // states
     let ylab_state 
         = Arc::new(Mutex::new(YLabState::Disconnected {ports: None}));
@@ -44,11 +240,15 @@ fn main() {
     // data channel for storage
     let (yldest_send, yldest_rec) 
         = channel();
+    // event markers, stored next to the data
+    let (mark_send, mark_rec) = channel();
+    let marks = Marks::new(mark_send);
 
     let ytf_wind 
-        = Arc::new(Mutex::new(make_banks(8, 5.0, YTF_WIND_LEN)));
+        = Arc::new(Mutex::new(make_banks(MAX_BANKS, 5.0, YTF_WIND_LEN)));
     let yld_wind 
         = Arc::new(Mutex::new(History::<Yld>::new(0..YLD_WIND_LEN,5.0)));
+    let quality = Arc::new(Mutex::new(Quality::new()));
 
 
     let ystud = Ystudio {
@@ -58,16 +258,33 @@ fn main() {
         yldest_cmd,
         yld_wind: yld_wind.clone(),
         ytf_wind: ytf_wind.clone(),
+        quality: quality.clone(),
+        marks,
         ui: Arc::new(Mutex::new(Yui {
                 selected_port: None,
                 selected_version: None,
+                detect_version: true,
                 selected_channels: [true; 8], // <-- crashes, when differently
                 selected_bank: 1,
                 view: DataView::Plot,
                 lowpass_threshold: 45.,
                 fft_min: 2.,
                 fft_max: 40.,
+                offline_range: None,
+                replay_path: None,
+                replay_speed: 1.0,
+                replay_loop: false,
+                sim_patterns: vec![],
+                participant: String::new(),
+                session: String::new(),
+                notes: String::new(),
+                mark_label: String::new(),
+                rec_format: Format::Yld,
+                rec_compression: Compression::None,
+                rec_rotation: Rotation::default(),
         })),
+        file_dialog: Arc::new(Mutex::new(None)),
+        offline: Arc::new(Mutex::new(None)),
     };
 
     // The ystudio object contains three coponents in a thread safe manner
@@ -80,6 +297,17 @@ fn main() {
     
     // The thread to collect Ylab data is started
     // consuming copies of ylab state, data and command listener
+    let quality_ylab = quality.clone();
+    let marks_ylab = ystud.marks.clone();
+    let yldest_cmd_ylab = ystud.yldest_cmd.clone();
+    // Live data for other programs
+    let live = Broadcast::new();
+    if cli.stream_port != 0 {
+        match serve_stream(cli.stream_port, live.clone()) {
+            Ok(()) => println!("Streaming live data on port {}", cli.stream_port),
+            Err(e) => eprintln!("No live stream on port {}: {}", cli.stream_port, e),
+        }
+    }
     thread::spawn(move || {
         ylab_thread(
             ylab_state,
@@ -87,6 +315,10 @@ fn main() {
             yld_wind,
             ytf_wind,
             yldest_send,
+            quality_ylab,
+            live,
+            marks_ylab,
+            yldest_cmd_ylab,
         );
     });
 
@@ -97,12 +329,67 @@ fn main() {
             yldest_state,
             yldest_listen,
             yldest_rec,
+            quality,
+            mark_rec,
         );
     });
 
-    // starting the egui, consuming the ystudio object.
-    // The details of the GUI are in ystudio.rs.
-    // The below works, because Ystudio objects implement eframe::App.
+    // Markers from stimulus software
+    if cli.trigger_port != 0 {
+        match listen_triggers(cli.trigger_port, ystud.marks.clone()) {
+            Ok(()) => println!("Listening for triggers on port {}", cli.trigger_port),
+            Err(e) => eprintln!("No triggers on port {}: {}", cli.trigger_port, e),
+        }
+    }
+
+    // Remote control by experiment runners
+    if cli.control_port != 0 {
+        let controls = Controls {
+            ylab_state: ystud.ylab_state.clone(),
+            ylab_cmd: ystud.ylab_cmd.clone(),
+            yldest_state: ystud.yldest_state.clone(),
+            yldest_cmd: ystud.yldest_cmd.clone(),
+        };
+        match serve_control(cli.control_port, controls) {
+            Ok(()) => println!("Remote control on port {}", cli.control_port),
+            Err(e) => eprintln!("No remote control on port {}: {}", cli.control_port, e),
+        }
+    }
 
-    ystudio::egui_init(ystud.clone());
+    match cli.command {
+        // recording without GUI, same threads and channels
+        Some(Command::Record {
+            port,
+            version,
+            out,
+            duration,
+            segment_mb,
+            segment_duration,
+            participant,
+            session,
+            notes,
+        }) => {
+            // the YLab goes into the metadata once connected
+            let meta = Meta {
+                participant,
+                session,
+                notes,
+                ..Default::default()
+            };
+            let rotation = Rotation {
+                max_bytes: segment_mb.map(|mb| mb * 1_000_000),
+                max_duration: segment_duration,
+            };
+            if let Err(e) = headless::record(&ystud, port, version, out, duration, rotation, meta) {
+                eprintln!("Recording failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        // handled before starting the threads
+        Some(Command::Convert { .. }) | Some(Command::Export { .. }) => {}
+        // starting the egui, consuming the ystudio object.
+        // The details of the GUI are in yui.rs.
+        // The below works, because Ystudio objects implement eframe::App.
+        None => yui::egui_init(ystud.clone()),
+    }
 }
@@ -0,0 +1,94 @@
+/// Event markers
+///
+/// Markers label moments in a session, e.g. stimulus onsets
+/// or condition changes. They are stamped with `ystudio_time`,
+/// the same clock as the data, and sent to Yldest, which writes
+/// them next to the recording, e.g. `1234.marks.csv` for `1234.yld`,
+/// with one marker per line: time in seconds and label.
+use crate::clock::ystudio_time;
+use crate::sinks;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Number of recent markers kept for display
+const RECENT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mark {
+    pub time: Duration,
+    pub label: String,
+}
+
+impl Mark {
+    /// Writing a marker as CSV line, the label is quoted if needed
+    pub fn to_csv_line(&self) -> String {
+        let label = if self.label.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", self.label.replace('"', "\"\""))
+        } else {
+            self.label.clone()
+        };
+        format!("{},{}\r\n", self.time.as_secs_f64(), label)
+    }
+
+    /// Reading a marker back from a line of a marks file
+    pub fn from_csv_line(line: &str) -> Option<Mark> {
+        let (time, label) = line.trim_end_matches(['\r', '\n']).split_once(',')?;
+        let time = time.parse::<f64>().ok().filter(|t| *t >= 0.0)?;
+        let label = match label.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\"\"", "\""),
+            None => label.to_string(),
+        };
+        Some(Mark {
+            time: Duration::from_secs_f64(time),
+            label,
+        })
+    }
+}
+
+/// Path of the markers of a recording
+pub fn marks_path(recording: &Path) -> PathBuf {
+    recording.with_file_name(format!("{}.marks.csv", sinks::stem(recording)))
+}
+
+/// Handle for setting markers
+///
+/// Can be cloned and shared, e.g. by the GUI and trigger listeners.
+#[derive(Clone)]
+pub struct Marks {
+    send: mpsc::Sender<Mark>,
+    recent: Arc<Mutex<Vec<Mark>>>, // for display
+}
+
+impl Marks {
+    /// Markers are sent to `send`, usually the Yldest thread
+    pub fn new(send: mpsc::Sender<Mark>) -> Self {
+        Marks {
+            send,
+            recent: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Setting a marker now
+    pub fn mark(&self, label: &str) -> Mark {
+        let mark = Mark {
+            time: ystudio_time(),
+            label: label.to_string(),
+        };
+        {
+            let mut recent = self.recent.lock().unwrap();
+            recent.push(mark.clone());
+            if recent.len() > RECENT {
+                recent.remove(0);
+            }
+        }
+        // without a receiver, markers are only shown
+        let _ = self.send.send(mark.clone());
+        mark
+    }
+
+    /// Recent markers, oldest first
+    pub fn recent(&self) -> Vec<Mark> {
+        self.recent.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,121 @@
+/// Recording metadata
+///
+/// Every recording gets a JSON sidecar next to it,
+/// e.g. `1234.yld` (or `1234.yld.zst`) has `1234.meta.json`.
+/// It is written when the recording starts, with the device
+/// and what the user entered (participant, session, notes),
+/// and finalised when the recording stops, with end time,
+/// sample counts and data quality.
+/// Participant, session and notes can be edited later by hand.
+use crate::clock::lsl_clock_start;
+use crate::quality::Quality;
+use crate::sinks;
+use crate::ylab::YLabVersion;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Meta {
+    pub ystudio: String, // version of Ystudio that recorded
+    pub device: Option<Device>,
+    pub participant: String,
+    pub session: String,
+    pub notes: String,
+    pub started: Option<String>, // wall clock, RFC 3339
+    pub stopped: Option<String>,
+    pub rows: u64,        // Yld rows written
+    pub quality: Quality, // data quality during the recording
+    pub lsl_clock: Option<f64>, // LSL clock at Ystudio time 0, for aligning exports
+    pub recovered: Option<String>, // when an interrupted recording was repaired
+    pub unrecoverable: Option<String>, // why an interrupted recording could not be repaired
+}
+
+/// The YLab a recording comes from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Device {
+    pub version: String,
+    pub port: String,
+    pub banks: Vec<BankInfo>,
+}
+
+/// One sensory bank of the YLab
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BankInfo {
+    pub label: String,
+    pub sensory: String,
+    pub channels: u8,
+    pub rate_hz: Option<usize>, // None for event banks
+    pub samples: u64,           // samples recorded
+}
+
+/// Path of the sidecar of a recording
+pub fn meta_path(recording: &Path) -> PathBuf {
+    recording.with_file_name(format!("{}.meta.json", sinks::stem(recording)))
+}
+
+/// Current wall clock time, RFC 3339 in UTC
+pub fn now() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+}
+
+impl Meta {
+    /// Metadata for recording from a YLab
+    pub fn new(version: YLabVersion, port: &str) -> Self {
+        let banks = version
+            .bank_labels()
+            .iter()
+            .zip(version.sensories())
+            .map(|(label, sensory)| BankInfo {
+                label: label.to_string(),
+                sensory: sensory.kind().to_string(),
+                channels: sensory.n_chan(),
+                rate_hz: sensory.hz(),
+                samples: 0,
+            })
+            .collect();
+        Meta {
+            ystudio: env!("CARGO_PKG_VERSION").to_string(),
+            device: Some(Device {
+                version: version.to_string(),
+                port: port.to_string(),
+                banks,
+            }),
+            lsl_clock: lsl_clock_start(),
+            ..Default::default()
+        }
+    }
+
+    /// Finalising on stop, with end time, counts and quality
+    pub fn finish(&mut self, quality: Quality) {
+        self.stopped = Some(now());
+        if let Some(device) = self.device.as_mut() {
+            for (bank, info) in device.banks.iter_mut().enumerate() {
+                info.samples = quality.banks.get(bank).map_or(0, |q| q.samples);
+            }
+        }
+        self.quality = quality;
+    }
+
+    /// Writing the sidecar of a recording, on disk when returning
+    ///
+    /// The sidecar is replaced in one step, so a crash leaves the old or the new one.
+    pub fn write(&self, recording: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let path = meta_path(recording);
+        let temp = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(temp, path)
+    }
+
+    /// Reading the sidecar of a recording
+    pub fn read(recording: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(meta_path(recording))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
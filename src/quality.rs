@@ -0,0 +1,211 @@
+/// Data quality
+///
+/// Counts problems in the incoming data, per sensory bank,
+/// using the device time stamps and the rate of the bank:
+///
+/// + gaps, where samples are missing (only banks with a fixed rate)
+/// + duplicates, samples with the same time stamp as the one before
+/// + out-of-order samples, older than the one before
+///
+/// Lines that could not be parsed cannot be attributed to a bank
+/// and are counted for the whole YLab.
+use crate::ylab::data::Ytf8;
+use crate::ylab::Sensory;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Tolerance for jitter of device time stamps (they have ms resolution)
+const JITTER: Duration = Duration::from_millis(1);
+/// A device clock going back by more than this has been restarted
+const RESTART: Duration = Duration::from_secs(1);
+
+/// Counters of one sensory bank
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BankQuality {
+    pub samples: u64,
+    pub gaps: u64,
+    pub missing: u64, // samples estimated to be lost in gaps
+    pub duplicates: u64,
+    pub out_of_order: u64,
+    #[serde(skip)]
+    last: Option<Duration>, // device time of the last sample in order
+}
+
+impl BankQuality {
+    fn check(&mut self, time: Duration, sensory: Option<Sensory>) {
+        self.samples += 1;
+        let Some(last) = self.last else {
+            self.last = Some(time);
+            return;
+        };
+        if time == last {
+            self.duplicates += 1;
+        } else if time + RESTART < last {
+            // device restarted (or a replay looped), starting over
+            self.last = Some(time);
+        } else if time < last {
+            self.out_of_order += 1;
+        } else {
+            if let Some(hz) = sensory.and_then(|s| s.hz()) {
+                let interval = Duration::from_secs_f64(1.0 / hz as f64);
+                let elapsed = time - last;
+                if elapsed > interval + interval / 2 + JITTER {
+                    self.gaps += 1;
+                    let lost = (elapsed.as_secs_f64() * hz as f64).round() as u64;
+                    self.missing += lost.saturating_sub(1);
+                }
+            }
+            self.last = Some(time);
+        }
+    }
+
+    /// Number of problems found
+    pub fn issues(&self) -> u64 {
+        self.gaps + self.duplicates + self.out_of_order
+    }
+}
+
+/// Counters of a YLab, one per bank
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Quality {
+    pub banks: Vec<BankQuality>, // by sensory number
+    pub parse_errors: u64,
+}
+
+impl Quality {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checking a sample against the previous one of its bank
+    ///
+    /// `sensory` gives the rate of the bank, if known
+    pub fn check(&mut self, sample: &Ytf8, sensory: Option<Sensory>) {
+        let bank = sample.sensory as usize;
+        if self.banks.len() <= bank {
+            self.banks.resize(bank + 1, BankQuality::default());
+        }
+        self.banks[bank].check(sample.time, sensory);
+    }
+
+    /// Counting a line (or bytes) that was not a sample
+    pub fn parse_error(&mut self) {
+        self.parse_errors += 1;
+    }
+
+    /// Counts since an earlier state, e.g. since the start of a recording
+    pub fn since(&self, earlier: &Quality) -> Quality {
+        let zero = BankQuality::default();
+        let banks = self
+            .banks
+            .iter()
+            .enumerate()
+            .map(|(bank, now)| {
+                let then = earlier.banks.get(bank).unwrap_or(&zero);
+                BankQuality {
+                    samples: now.samples.saturating_sub(then.samples),
+                    gaps: now.gaps.saturating_sub(then.gaps),
+                    missing: now.missing.saturating_sub(then.missing),
+                    duplicates: now.duplicates.saturating_sub(then.duplicates),
+                    out_of_order: now.out_of_order.saturating_sub(then.out_of_order),
+                    last: now.last,
+                }
+            })
+            .collect();
+        Quality {
+            banks,
+            parse_errors: self.parse_errors.saturating_sub(earlier.parse_errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checking samples of bank 1 at the given device times in ms
+    fn checked(millis: &[u64], sensory: Option<Sensory>) -> BankQuality {
+        let mut quality = Quality::new();
+        for ms in millis {
+            let sample = Ytf8 {
+                sensory: 1,
+                time: Duration::from_millis(*ms),
+                ..Default::default()
+            };
+            quality.check(&sample, sensory);
+        }
+        quality.banks[1].clone()
+    }
+
+    const ADC_100: Option<Sensory> = Some(Sensory::Adc(3, 100));
+
+    #[test]
+    fn steady_rate_has_no_issues() {
+        let bank = checked(&[0, 10, 20, 31, 40, 50], ADC_100);
+        assert_eq!(bank.samples, 6);
+        assert_eq!(bank.issues(), 0);
+    }
+
+    #[test]
+    fn gaps_and_missing_samples() {
+        // 30 and 40 are missing, then 70 to 90
+        let bank = checked(&[0, 10, 20, 50, 60, 100], ADC_100);
+        assert_eq!(bank.gaps, 2);
+        assert_eq!(bank.missing, 5);
+        assert_eq!(bank.duplicates, 0);
+        assert_eq!(bank.out_of_order, 0);
+    }
+
+    #[test]
+    fn no_gaps_without_rate() {
+        // event banks send when something happens
+        let bank = checked(&[0, 10, 500, 2000], Some(Sensory::Moi(4)));
+        assert_eq!(bank.issues(), 0);
+        let bank = checked(&[0, 10, 500, 2000], None);
+        assert_eq!(bank.issues(), 0);
+    }
+
+    #[test]
+    fn duplicates() {
+        let bank = checked(&[0, 10, 10, 20, 20, 20, 30], ADC_100);
+        assert_eq!(bank.duplicates, 3);
+        assert_eq!(bank.gaps, 0);
+        assert_eq!(bank.out_of_order, 0);
+    }
+
+    #[test]
+    fn out_of_order() {
+        // 20 is late, the gap it leaves is counted too
+        let bank = checked(&[0, 10, 30, 20, 40], ADC_100);
+        assert_eq!(bank.out_of_order, 1);
+        assert_eq!(bank.gaps, 1);
+        assert_eq!(bank.duplicates, 0);
+    }
+
+    #[test]
+    fn restart_is_not_out_of_order() {
+        let bank = checked(&[5000, 5010, 0, 10, 20], ADC_100);
+        assert_eq!(bank.issues(), 0);
+    }
+
+    #[test]
+    fn counts_since_start_of_recording() {
+        let mut quality = Quality::new();
+        quality.parse_error();
+        let sample = |ms| Ytf8 {
+            sensory: 0,
+            time: Duration::from_millis(ms),
+            ..Default::default()
+        };
+        quality.check(&sample(0), ADC_100);
+        quality.check(&sample(0), ADC_100);
+        let start = quality.clone();
+        quality.check(&sample(10), ADC_100);
+        quality.check(&sample(10), ADC_100);
+        quality.parse_error();
+        let since = quality.since(&start);
+        assert_eq!(since.banks[0].samples, 2);
+        assert_eq!(since.banks[0].duplicates, 1);
+        assert_eq!(since.parse_errors, 1);
+    }
+}
@@ -0,0 +1,517 @@
+/// Recovering interrupted recordings
+///
+/// A recording whose sidecar has no stop time was interrupted,
+/// e.g. by a crash, a power cut or a killed process.
+/// Its files can end in the middle of a line, or of a compressed block.
+/// At startup, Ystudio repairs such recordings in the directories it recorded to:
+/// + a truncated last line is cut off (Yld, wide CSV, markers)
+/// + compressed files are compressed again, up to where they could be read,
+///   so that other tools can unpack them
+/// + the sidecar gets the number of rows found and the time of recovery
+///
+/// Parquet files are only readable once finished, an interrupted one is lost.
+/// Segments of a segmented recording are repaired one by one.
+/// A last segment that cannot be read (Parquet) is set aside
+/// (`p01-003.parquet.broken`), and the manifest is rewritten
+/// with the rows and time stamps of the segments that are left.
+/// Recordings that cannot be repaired are noted as such in the sidecar,
+/// and left alone from then on.
+///
+/// Recordings being written hold a lock (see `RecordingLock`),
+/// so that recordings of another running Ystudio are not touched.
+use crate::marks::marks_path;
+use crate::meta::{self, Meta};
+use crate::segments::{self, Manifest};
+use crate::sinks::{self, Compression, Format};
+use crate::yldfile::{self, for_each_row};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of recording directories remembered
+const MAX_DIRS: usize = 20;
+
+/// What was done to an interrupted recording
+#[derive(Debug, Clone, Default)]
+pub struct Recovered {
+    pub files: usize,               // files repaired
+    pub cut: usize,                 // bytes of broken lines cut off
+    pub rows: u64,                  // rows that could be read
+    pub set_aside: Option<PathBuf>, // unreadable last segment
+}
+
+/// Lock of a recording while it is written
+///
+/// The writer holds an OS file lock on `<stem>.lock` next to the recording.
+/// The OS releases it when the process ends, also by a crash,
+/// so a lock that can be taken means nobody is writing anymore.
+/// The file holds the process id, for people looking.
+#[derive(Debug)]
+pub struct RecordingLock {
+    path: PathBuf,
+    _file: File, // locked while open
+}
+
+/// Path of the lock of a recording
+pub fn lock_path(recording: &Path) -> PathBuf {
+    recording.with_file_name(format!("{}.lock", sinks::stem(recording)))
+}
+
+impl RecordingLock {
+    /// Locking a recording, fails with `WouldBlock` if another process holds it
+    pub fn acquire(recording: &Path) -> io::Result<Self> {
+        let path = lock_path(recording);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+        file.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "recording is written by another process",
+            ),
+            TryLockError::Error(e) => e,
+        })?;
+        file.set_len(0)?;
+        file.write_all(format!("{}\n", std::process::id()).as_bytes())?;
+        Ok(RecordingLock { path, _file: file })
+    }
+}
+
+impl Drop for RecordingLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// File listing the directories recorded to, in the home directory
+fn dirs_file() -> Option<PathBuf> {
+    std::env::home_dir().map(|home| home.join(".ystudio").join("recording_dirs"))
+}
+
+/// Noting a directory recorded to, for recovery at the next start
+pub fn remember_dir(dir: &Path) -> io::Result<()> {
+    let Some(file) = dirs_file() else {
+        return Ok(());
+    };
+    let dir = dir.canonicalize()?;
+    let mut dirs: Vec<PathBuf> = remembered_dirs()
+        .into_iter()
+        .filter(|known| *known != dir)
+        .collect();
+    dirs.push(dir);
+    let skip = dirs.len().saturating_sub(MAX_DIRS);
+    let lines: String = dirs[skip..]
+        .iter()
+        .map(|dir| format!("{}\n", dir.to_string_lossy()))
+        .collect();
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(file, lines)
+}
+
+fn remembered_dirs() -> Vec<PathBuf> {
+    dirs_file()
+        .and_then(|file| fs::read_to_string(file).ok())
+        .map(|lines| lines.lines().map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+/// Directories to look for interrupted recordings in:
+/// the working directory and the ones recorded to
+pub fn recording_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = vec![];
+    let current = std::env::current_dir().and_then(|dir| dir.canonicalize());
+    for dir in current.into_iter().chain(remembered_dirs()) {
+        if dir.is_dir() && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    dirs
+}
+
+/// Repairing all interrupted recordings in a directory
+///
+/// Recordings locked by a running writer are skipped.
+/// Returns the recordings found, with what was done or why not.
+pub fn recover_dir(dir: &Path) -> Vec<(PathBuf, io::Result<Recovered>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut out = vec![];
+    for path in entries.map_while(Result::ok).map(|entry| entry.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(stem) = name.strip_suffix(".meta.json") else {
+            continue;
+        };
+        // sidecars are found by the recording's name
+        let Ok(meta) = Meta::read(&dir.join(stem)) else {
+            continue;
+        };
+        if !is_interrupted(&meta) {
+            continue;
+        }
+        let Some(recording) = find_recording(dir, stem) else {
+            continue;
+        };
+        // still being written
+        let Ok(_lock) = RecordingLock::acquire(&recording) else {
+            continue;
+        };
+        // the writer may have finished just before
+        let Ok(meta) = Meta::read(&recording).map(|meta| (is_interrupted(&meta), meta)) else {
+            continue;
+        };
+        let (true, meta) = meta else {
+            continue;
+        };
+        let result = recover(&recording, meta.clone());
+        // not trying again at every start
+        if let Err(e) = result.as_ref() {
+            let meta = Meta {
+                unrecoverable: Some(e.to_string()),
+                ..meta
+            };
+            if let Err(e) = meta.write(&recording) {
+                eprintln!("Could not write metadata of {:?}: {}", recording, e);
+            }
+        }
+        out.push((recording, result));
+    }
+    out
+}
+
+/// Has the recording been interrupted, and not been dealt with since?
+fn is_interrupted(meta: &Meta) -> bool {
+    meta.stopped.is_none() && meta.recovered.is_none() && meta.unrecoverable.is_none()
+}
+
+/// The recording of a sidecar: a manifest, or a file with the same stem
+fn find_recording(dir: &Path, stem: &str) -> Option<PathBuf> {
+    let manifest = segments::manifest_path(&dir.join(stem));
+    if manifest.is_file() {
+        return Some(manifest);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .map_while(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            let known = sinks::uncompressed(path)
+                .extension()
+                .is_some_and(|ext| ext == "yld" || ext == "csv" || ext == "parquet");
+            known && sinks::stem(path) == stem && path.is_file()
+        })
+}
+
+/// Repairing one interrupted recording and noting it in its sidecar
+pub fn recover(recording: &Path, mut meta: Meta) -> io::Result<Recovered> {
+    let mut recovered = Recovered::default();
+    match segments::is_manifest(recording) {
+        true => recover_segments(recording, &mut recovered)?,
+        false => recover_file(recording, &mut recovered)?,
+    }
+    let marks = marks_path(recording);
+    if marks.is_file() {
+        recovered.cut += repair_text(&marks)?.unwrap_or(0);
+    }
+    for_each_row(recording, |_| {
+        recovered.rows += 1;
+        Ok(())
+    })?;
+    meta.rows = recovered.rows;
+    meta.recovered = Some(meta::now());
+    meta.write(recording)?;
+    Ok(recovered)
+}
+
+/// Repairing a file of a recording, Parquet files are only checked
+fn recover_file(file: &Path, recovered: &mut Recovered) -> io::Result<()> {
+    let cut = match Format::from_path(file) {
+        Format::Parquet if sinks::is_finished_parquet(file) => None,
+        Format::Parquet => return Err(io::Error::other("Parquet file was not finished")),
+        _ => repair_text(file)?,
+    };
+    if let Some(cut) = cut {
+        recovered.files += 1;
+        recovered.cut += cut;
+    }
+    Ok(())
+}
+
+/// Repairing the segments of a recording and rewriting its manifest
+///
+/// A last segment that cannot be repaired is set aside,
+/// the segments left are complete.
+fn recover_segments(manifest_path: &Path, recovered: &mut Recovered) -> io::Result<()> {
+    let mut manifest = Manifest::read(manifest_path)?;
+    let paths = manifest.paths(manifest_path);
+    let n_segments = paths.len();
+    let mut kept = vec![];
+    for (n, (mut segment, path)) in manifest.segments.into_iter().zip(paths).enumerate() {
+        match recover_file(&path, recovered) {
+            Ok(()) => {}
+            Err(_) if n + 1 == n_segments => {
+                if path.is_file() {
+                    let aside = path.with_file_name(format!(
+                        "{}.broken",
+                        path.file_name().unwrap_or_default().to_string_lossy()
+                    ));
+                    fs::rename(&path, &aside)?;
+                    recovered.set_aside = Some(aside);
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        segment.rows = 0;
+        segment.first = None;
+        segment.last = None;
+        for_each_row(&path, |yld| {
+            let time = yld.time.as_secs_f64();
+            segment.first.get_or_insert(time);
+            segment.last = Some(time);
+            segment.rows += 1;
+            Ok(())
+        })?;
+        segment.complete = true;
+        kept.push(segment);
+    }
+    manifest.segments = kept;
+    manifest.write(manifest_path)
+}
+
+/// Cutting off a broken last line
+///
+/// Compressed files are read up to where they break,
+/// and written again if they did.
+/// Returns the bytes cut, None if the file was fine.
+fn repair_text(path: &Path) -> io::Result<Option<usize>> {
+    let compression = Compression::from_path(path);
+    if compression == Compression::None {
+        let data = fs::read(path)?;
+        let keep = complete_lines(&data);
+        if keep == data.len() {
+            return Ok(None);
+        }
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(keep as u64)?;
+        file.sync_all()?;
+        return Ok(Some(data.len() - keep));
+    }
+    let mut data = vec![];
+    // what could be read stays in data
+    let broken = yldfile::open(path)?.read_to_end(&mut data).is_err();
+    let keep = complete_lines(&data);
+    if !broken && keep == data.len() {
+        return Ok(None);
+    }
+    let temp = path.with_file_name(format!(
+        ".{}.recovered",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    sinks::write_file(&temp, compression, &data[..keep])?;
+    fs::rename(temp, path)?;
+    Ok(Some(data.len() - keep))
+}
+
+/// Length of the data up to the end of the last complete line
+fn complete_lines(data: &[u8]) -> usize {
+    data.iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segments::Rotation;
+    use crate::ylab::data::Yld;
+    use crate::ylab::YLabVersion;
+    use std::time::Duration;
+
+    /// An empty directory for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ystudio-recovery-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Rows of `n` samples, 100 per second
+    fn rows(n: u64) -> Vec<Yld> {
+        (0..n)
+            .flat_map(|sample| {
+                let time = Duration::from_millis(10 * sample);
+                (0..8).map(move |chan| Yld {
+                    time,
+                    dev: 0,
+                    sensory: 1,
+                    chan,
+                    value: chan as f64,
+                    dev_time: time,
+                    arrival: time,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unreadable_last_segment_is_set_aside() {
+        let dir = test_dir("segments");
+        let meta = Meta::new(YLabVersion::Go, "Simulator");
+        let rotation = Rotation {
+            max_bytes: None,
+            max_duration: Some(Duration::from_secs(1)),
+        };
+        let (manifest, mut sink) = segments::create(
+            &dir.join("p01.parquet"),
+            Format::Parquet,
+            Compression::None,
+            rotation,
+            &meta,
+        )
+        .unwrap();
+        meta.write(&manifest).unwrap();
+        // 2.5 s, the third segment is never finished
+        for row in rows(250) {
+            sink.write(&row).unwrap();
+        }
+        std::mem::forget(sink);
+        let written = Manifest::read(&manifest).unwrap().segments;
+        assert_eq!(written.len(), 3);
+        assert!(written[0].complete && written[1].complete && !written[2].complete);
+        let finished = written[0].rows + written[1].rows;
+
+        // the finished segments can be read before recovery
+        let mut before = 0;
+        let skipped = for_each_row(&manifest, |_| {
+            before += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!((before, skipped), (finished, 1));
+
+        let recovered = recover(&manifest, meta).unwrap();
+        assert_eq!(recovered.rows, finished);
+        let aside = recovered.set_aside.unwrap();
+        assert_eq!(aside, dir.join("p01-003.parquet.broken"));
+        assert!(aside.is_file() && !dir.join("p01-003.parquet").exists());
+        let segments = Manifest::read(&manifest).unwrap().segments;
+        assert_eq!(segments.len(), 2);
+        // as written before the crash
+        for (segment, written) in segments.iter().zip(&written) {
+            assert!(segment.complete);
+            assert_eq!(segment.rows, written.rows);
+            assert_eq!((segment.first, segment.last), (written.first, written.last));
+        }
+        assert_eq!(segments[0].first, Some(0.0));
+        let meta = Meta::read(&manifest).unwrap();
+        assert!(meta.recovered.is_some() && meta.unrecoverable.is_none());
+        assert_eq!(meta.rows, finished);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Yld text of `n` samples
+    fn yld_text(n: u64) -> String {
+        rows(n).iter().map(|row| row.to_csv_line() + "\n").collect()
+    }
+
+    /// An interrupted recording: a sidecar without stop time
+    fn interrupted(recording: &Path) {
+        Meta::new(YLabVersion::Go, "Simulator")
+            .write(recording)
+            .unwrap();
+    }
+
+    #[test]
+    fn complete_lines_end_at_the_last_newline() {
+        assert_eq!(complete_lines(b""), 0);
+        assert_eq!(complete_lines(b"1,0"), 0);
+        assert_eq!(complete_lines(b"1,0\n2,0"), 4);
+        assert_eq!(complete_lines(b"1,0\n2,0\n"), 8);
+    }
+
+    #[test]
+    fn broken_last_line_is_cut() {
+        let dir = test_dir("plain");
+        let path = dir.join("p01.yld");
+        let text = yld_text(10);
+        fs::write(&path, format!("{}1.5,0,1,", text)).unwrap();
+        assert_eq!(repair_text(&path).unwrap(), Some(8));
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        // nothing more to do
+        assert_eq!(repair_text(&path).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_compressed_files_are_compressed_again() {
+        let dir = test_dir("compressed");
+        let text = yld_text(20_000);
+        for name in ["p01.yld.zst", "p01.yld.gz"] {
+            let path = dir.join(name);
+            sinks::write_file(&path, Compression::from_path(&path), text.as_bytes()).unwrap();
+            assert_eq!(repair_text(&path).unwrap(), None, "{}", name);
+            // cut off in the middle of a block
+            let bytes = fs::read(&path).unwrap();
+            fs::write(&path, &bytes[..bytes.len() * 3 / 4]).unwrap();
+            assert!(repair_text(&path).unwrap().is_some(), "{}", name);
+            let mut repaired = String::new();
+            yldfile::open(&path)
+                .unwrap()
+                .read_to_string(&mut repaired)
+                .unwrap();
+            assert!(!repaired.is_empty() && repaired.ends_with('\n'), "{}", name);
+            assert!(text.starts_with(&repaired), "{}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn locked_recordings_are_left_alone() {
+        let dir = test_dir("locked");
+        let path = dir.join("p01.yld");
+        let broken = format!("{}1.5,0,1,", yld_text(10));
+        fs::write(&path, &broken).unwrap();
+        interrupted(&path);
+        // still being written
+        let lock = RecordingLock::acquire(&path).unwrap();
+        assert!(recover_dir(&dir).is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+        drop(lock);
+
+        let recovered = recover_dir(&dir);
+        assert_eq!(recovered.len(), 1);
+        let (recording, result) = &recovered[0];
+        assert_eq!(recording, &path);
+        let result = result.as_ref().unwrap();
+        assert_eq!((result.files, result.cut, result.rows), (1, 8, 80));
+        let meta = Meta::read(&path).unwrap();
+        assert!(meta.recovered.is_some());
+        assert_eq!(meta.rows, 80);
+        assert!(!lock_path(&path).exists());
+        // dealt with
+        assert!(recover_dir(&dir).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unfinished_parquet_is_unrecoverable() {
+        let dir = test_dir("parquet");
+        let path = dir.join("p01.parquet");
+        fs::write(&path, b"PAR1 cut off").unwrap();
+        interrupted(&path);
+        let recovered = recover_dir(&dir);
+        assert_eq!(recovered.len(), 1);
+        assert!(recovered[0].1.is_err());
+        let meta = Meta::read(&path).unwrap();
+        assert!(meta.unrecoverable.is_some() && meta.recovered.is_none());
+        // not tried again
+        assert!(recover_dir(&dir).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use crate::sources::{DataSource, Reading};
+/// Replay of recordings
+///
+/// feeds the samples of a recorded Yld or Ytf file
+/// into the YLab thread, as if they came from a YLab.
+/// Playback runs in real time or accelerated and can loop.
+use crate::ylab::data::Ytf8;
+use crate::yldfile::read_samples;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub struct Replay {
+    path: PathBuf,
+    samples: Vec<Ytf8>, // in order of time
+    pos: usize,         // next sample to play
+    speed: f64,         // 1.0 is real time
+    looping: bool,
+    started: Instant, // wall clock at the start of the current pass
+}
+
+impl Replay {
+    /// Opening a recording for replay
+    pub fn open(path: &Path, speed: f64, looping: bool) -> io::Result<Self> {
+        let (samples, _) = read_samples(path)?;
+        if samples.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no samples in recording",
+            ));
+        }
+        let mut replay = Self::from_samples(samples, speed, looping);
+        replay.path = path.to_path_buf();
+        Ok(replay)
+    }
+
+    pub fn from_samples(mut samples: Vec<Ytf8>, speed: f64, looping: bool) -> Self {
+        samples.sort_by_key(|sample| sample.time);
+        Replay {
+            path: PathBuf::new(),
+            samples,
+            pos: 0,
+            speed: speed.max(f64::EPSILON),
+            looping,
+            started: Instant::now(),
+        }
+    }
+
+    /// Time of a sample, relative to the first and scaled by speed
+    fn due_at(&self, sample: &Ytf8) -> Duration {
+        let since_first = sample.time.saturating_sub(self.samples[0].time);
+        since_first.div_f64(self.speed)
+    }
+
+    /// The next sample, once it is due
+    ///
+    /// returns None while the next sample is not yet due
+    /// and after the last sample (unless looping)
+    pub fn next_sample(&mut self) -> Option<Ytf8> {
+        if self.pos >= self.samples.len() {
+            if !self.looping {
+                return None;
+            }
+            self.pos = 0;
+            self.started = Instant::now();
+        }
+        let sample = self.samples[self.pos];
+        if self.due_at(&sample) > self.started.elapsed() {
+            return None;
+        }
+        self.pos += 1;
+        Some(sample)
+    }
+
+    /// All samples have been played, and no looping
+    pub fn finished(&self) -> bool {
+        !self.looping && self.pos >= self.samples.len()
+    }
+}
+
+impl DataSource for Replay {
+    fn name(&self) -> String {
+        let file_name = self.path.file_name().unwrap_or_default();
+        format!("Replay {}", file_name.to_string_lossy())
+    }
+
+    fn next_sample(&mut self) -> Reading {
+        match Replay::next_sample(self) {
+            Some(sample) => Reading::Sample(sample),
+            None if self.finished() => Reading::Closed,
+            None => Reading::Empty,
+        }
+    }
+}
@@ -0,0 +1,407 @@
+/// Segmented recordings
+///
+/// Multi-hour recordings can be split into numbered segments,
+/// rolling over to a new file after a size or a duration,
+/// e.g. `p01-001.yld.zst`, `p01-002.yld.zst`, ... for `p01.yld.zst`.
+/// A manifest (`p01.manifest.json`) lists the segments in order,
+/// with their first and last time stamps and row counts.
+/// It is rewritten whenever a segment starts or is complete,
+/// so a crash or a full disk loses at most the current segment.
+///
+/// Metadata and markers belong to the whole recording (`p01.meta.json`,
+/// `p01.marks.csv`). Ystudio reads the manifest like a recording
+/// (see `yldfile::for_each_row`), for viewing, replay, conversion and export.
+use crate::meta::Meta;
+use crate::sinks::{self, Compression, Format, Sink};
+use crate::ylab::data::Yld;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// End of manifest file names
+pub const MANIFEST: &str = ".manifest.json";
+/// Rows between checks of the segment size
+const SIZE_CHECK: u64 = 1000;
+
+/// When to roll over to the next segment, never by default
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_duration: Option<Duration>,
+}
+
+impl Rotation {
+    pub fn is_off(&self) -> bool {
+        self.max_bytes.is_none() && self.max_duration.is_none()
+    }
+}
+
+/// List of the segments of a recording
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Manifest {
+    pub max_bytes: Option<u64>,
+    pub max_secs: Option<f64>,
+    pub segments: Vec<Segment>,
+}
+
+/// One segment file, named relative to the manifest
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Segment {
+    pub file: String,
+    pub first: Option<f64>, // time stamps in seconds
+    pub last: Option<f64>,
+    pub rows: u64,
+    pub complete: bool, // false while written, or after a crash
+}
+
+/// Is the file the manifest of a segmented recording?
+pub fn is_manifest(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(MANIFEST)
+}
+
+/// Path of the manifest of a recording
+pub fn manifest_path(recording: &Path) -> PathBuf {
+    recording.with_file_name(format!("{}{}", sinks::stem(recording), MANIFEST))
+}
+
+/// Path of segment `n` (from 1) of a recording
+///
+/// The segment keeps the extensions of the recording,
+/// e.g. `p01-002.yld.zst` for `p01.yld.zst`.
+pub fn segment_path(recording: &Path, n: usize) -> PathBuf {
+    let name = recording.file_name().unwrap_or_default().to_string_lossy();
+    let stem = sinks::stem(recording);
+    let extensions = name.strip_prefix(stem.as_str()).unwrap_or_default();
+    recording.with_file_name(format!("{}-{:03}{}", stem, n, extensions))
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Writing a new version next to the old one, then replacing it,
+    /// so there always is a complete manifest
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let temp = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(temp, path)
+    }
+
+    /// Paths of the segment files, in order
+    pub fn paths(&self, manifest: &Path) -> Vec<PathBuf> {
+        self.segments
+            .iter()
+            .map(|segment| manifest.with_file_name(&segment.file))
+            .collect()
+    }
+}
+
+/// Creating the file(s) of a recording
+///
+/// Without rotation, this is a single file, as `sinks::create`.
+/// With rotation, the first segment and the manifest are created.
+/// Existing files are not overwritten, creating fails instead.
+/// `meta` goes into completed segments, where the format has room.
+/// Returns the path of the recording (the manifest, if segmented) and the sink.
+pub fn create(
+    recording: &Path,
+    format: Format,
+    compression: Compression,
+    rotation: Rotation,
+    meta: &Meta,
+) -> io::Result<(PathBuf, Box<dyn Sink>)> {
+    if rotation.is_off() {
+        claim(recording)?;
+        let sink = sinks::create(recording, format, compression)?;
+        return Ok((recording.to_path_buf(), sink));
+    }
+    let manifest = Manifest {
+        max_bytes: rotation.max_bytes,
+        max_secs: rotation.max_duration.map(|d| d.as_secs_f64()),
+        segments: vec![],
+    };
+    let mut sink = SegmentedSink {
+        recording: recording.to_path_buf(),
+        manifest_path: manifest_path(recording),
+        manifest,
+        format,
+        compression,
+        rotation,
+        meta: meta.clone(),
+        current: None,
+        last: None,
+        unchecked: 0,
+    };
+    claim(&sink.manifest_path)?;
+    if let Err(e) = sink.next_segment() {
+        // the manifest is still empty
+        let _ = fs::remove_file(&sink.manifest_path);
+        return Err(e);
+    }
+    Ok((sink.manifest_path.clone(), Box::new(sink)))
+}
+
+/// Claiming the name of a new file, failing if it exists
+fn claim(path: &Path) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map(|_| ())
+}
+
+/// Rows go into the current segment, until it is full
+pub struct SegmentedSink {
+    recording: PathBuf,
+    manifest_path: PathBuf,
+    manifest: Manifest,
+    format: Format,
+    compression: Compression,
+    rotation: Rotation,
+    meta: Meta,
+    current: Option<Box<dyn Sink>>,
+    last: Option<Yld>, // last row written, samples are never split
+    unchecked: u64,    // rows since the size was checked
+}
+
+impl SegmentedSink {
+    /// Completing the current segment, if any, and starting the next
+    fn next_segment(&mut self) -> io::Result<()> {
+        if let Some(sink) = self.current.take() {
+            sink.finish(&self.meta)?;
+            self.complete_segment()?;
+        }
+        let path = segment_path(&self.recording, self.manifest.segments.len() + 1);
+        claim(&path)?;
+        self.current = Some(sinks::create(&path, self.format, self.compression)?);
+        self.manifest.segments.push(Segment {
+            file: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        });
+        self.manifest.write(&self.manifest_path)
+    }
+
+    fn complete_segment(&mut self) -> io::Result<()> {
+        if let Some(segment) = self.manifest.segments.last_mut() {
+            segment.complete = true;
+        }
+        self.manifest.write(&self.manifest_path)
+    }
+
+    /// Is the current segment full, by duration or size?
+    fn is_full(&mut self) -> bool {
+        let Some(segment) = self.manifest.segments.last() else {
+            return false;
+        };
+        let by_duration = match (self.rotation.max_duration, segment.first, segment.last) {
+            (Some(max), Some(first), Some(last)) => last - first >= max.as_secs_f64(),
+            _ => false,
+        };
+        let by_size = match self.rotation.max_bytes {
+            Some(max) if self.unchecked >= SIZE_CHECK => {
+                self.unchecked = 0;
+                let path = self.manifest_path.with_file_name(&segment.file);
+                fs::metadata(path).is_ok_and(|file| file.len() >= max)
+            }
+            _ => false,
+        };
+        by_duration || by_size
+    }
+}
+
+impl Sink for SegmentedSink {
+    fn write(&mut self, yld: &Yld) -> io::Result<()> {
+        let new_sample = self.last.is_none_or(|last| {
+            last.time != yld.time || last.dev != yld.dev || last.sensory != yld.sensory
+        });
+        if new_sample && self.is_full() {
+            self.next_segment()?;
+        }
+        if let Some(sink) = self.current.as_mut() {
+            sink.write(yld)?;
+        }
+        if let Some(segment) = self.manifest.segments.last_mut() {
+            let time = yld.time.as_secs_f64();
+            segment.first.get_or_insert(time);
+            segment.last = Some(time);
+            segment.rows += 1;
+        }
+        self.last = Some(*yld);
+        self.unchecked += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(sink) => sink.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self: Box<Self>, meta: &Meta) -> io::Result<()> {
+        if let Some(sink) = self.current.take() {
+            sink.finish(meta)?;
+        }
+        self.complete_segment()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ylab::YLabVersion;
+
+    /// An empty directory for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ystudio-segments-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Rows of `n` samples of `chans` channels, 100 per second
+    fn rows(n: u64, chans: u8) -> Vec<Yld> {
+        (0..n)
+            .flat_map(|sample| {
+                let time = Duration::from_millis(10 * sample);
+                (0..chans).map(move |chan| Yld {
+                    time,
+                    dev: 0,
+                    sensory: 1,
+                    chan,
+                    value: chan as f64,
+                    dev_time: time,
+                    arrival: time,
+                })
+            })
+            .collect()
+    }
+
+    /// Recording `rows` in segments, returning the manifest path and the manifest
+    fn record(dir: &Path, rotation: Rotation, rows: &[Yld]) -> (PathBuf, Manifest) {
+        let meta = Meta::new(YLabVersion::Go, "Simulator");
+        let (path, mut sink) = create(
+            &dir.join("p01.yld"),
+            Format::Yld,
+            Compression::None,
+            rotation,
+            &meta,
+        )
+        .unwrap();
+        for row in rows {
+            sink.write(row).unwrap();
+        }
+        sink.finish(&meta).unwrap();
+        let manifest = Manifest::read(&path).unwrap();
+        (path, manifest)
+    }
+
+    /// Every segment holds whole samples, in order
+    fn assert_whole_samples(manifest: &Manifest, chans: u64) {
+        for segment in &manifest.segments {
+            assert!(segment.complete, "{}", segment.file);
+            assert_eq!(segment.rows % chans, 0, "{}", segment.file);
+        }
+        for pair in manifest.segments.windows(2) {
+            assert!(pair[0].last.unwrap() < pair[1].first.unwrap());
+        }
+    }
+
+    #[test]
+    fn segments_keep_the_extensions() {
+        let recording = Path::new("data/p01.yld.zst");
+        assert_eq!(
+            segment_path(recording, 2),
+            Path::new("data/p01-002.yld.zst")
+        );
+        assert_eq!(
+            manifest_path(recording),
+            Path::new("data/p01.manifest.json")
+        );
+        assert_eq!(
+            segment_path(Path::new("p01.parquet"), 12),
+            Path::new("p01-012.parquet")
+        );
+        assert!(is_manifest(&manifest_path(recording)));
+        assert!(!is_manifest(recording));
+    }
+
+    #[test]
+    fn rollover_by_duration() {
+        let dir = test_dir("duration");
+        let rotation = Rotation {
+            max_bytes: None,
+            max_duration: Some(Duration::from_secs(1)),
+        };
+        // 2.5 s
+        let (path, manifest) = record(&dir, rotation, &rows(250, 8));
+        assert_eq!(manifest.max_secs, Some(1.0));
+        assert_eq!(manifest.segments.len(), 3);
+        assert_whole_samples(&manifest, 8);
+        let segment = &manifest.segments[0];
+        assert_eq!(segment.file, "p01-001.yld");
+        assert_eq!(segment.first, Some(0.0));
+        assert!(segment.last.unwrap() >= 1.0);
+        let segment = &manifest.segments[2];
+        assert_eq!(segment.last, Some(2.49));
+        let rows: u64 = manifest.segments.iter().map(|segment| segment.rows).sum();
+        assert_eq!(rows, 2000);
+        for file in manifest.paths(&path) {
+            assert!(file.exists(), "{}", file.display());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rollover_by_size() {
+        let dir = test_dir("size");
+        let rotation = Rotation {
+            max_bytes: Some(1000),
+            max_duration: None,
+        };
+        // samples of 3 rows, the size is checked within a sample
+        let (_, manifest) = record(&dir, rotation, &rows(1000, 3));
+        assert_eq!(manifest.max_bytes, Some(1000));
+        assert_eq!(manifest.segments.len(), 3);
+        assert_whole_samples(&manifest, 3);
+        assert!(manifest.segments[0].rows > SIZE_CHECK);
+        let rows: u64 = manifest.segments.iter().map(|segment| segment.rows).sum();
+        assert_eq!(rows, 3000);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn existing_recordings_are_not_overwritten() {
+        let dir = test_dir("existing");
+        let rotation = Rotation {
+            max_bytes: None,
+            max_duration: Some(Duration::from_secs(1)),
+        };
+        record(&dir, rotation, &rows(10, 8));
+        let meta = Meta::new(YLabVersion::Go, "Simulator");
+        let recording = dir.join("p01.yld");
+        assert!(create(&recording, Format::Yld, Compression::None, rotation, &meta).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,787 @@
+/// Recording sinks
+///
+/// Yldest writes the rows of a recording into a sink,
+/// which decides on the file format:
+/// + Yld: long CSV, one value per line (the default)
+/// + Wide CSV: one sample per line, with all channels of the bank
+///   and a header row, about a fifth of the size of Yld
+///   (channels missing from a sample stay empty)
+/// + Parquet: the same columns as Yld, typed and compressed,
+///   for Arrow-based tools (pandas, polars, R arrow, DuckDB)
+///
+/// Parquet files carry the recording metadata (as in the sidecar)
+/// as JSON under the key `ystudio.meta` of the file metadata,
+/// which Arrow readers show as schema metadata.
+///
+/// Yld and wide CSV can be compressed on the fly with gzip or zstd
+/// (`.yld.gz`, `.yld.zst`, `.csv.zst`, ...). Ystudio reads them back
+/// transparently (see `yldfile::open`), so do pandas and polars.
+/// Parquet compresses by itself and is never compressed again.
+use crate::meta::Meta;
+use crate::segments;
+use crate::ylab::data::Yld;
+use crate::yldfile::for_each_row;
+use flate2::write::GzEncoder;
+use parquet::data_type::{DoubleType, Int32Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::format::KeyValue;
+use parquet::record::RowAccessor;
+use parquet::schema::parser::parse_message_type;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Key of the recording metadata in Parquet files
+pub const META_KEY: &str = "ystudio.meta";
+/// Rows per Parquet row group
+const ROW_GROUP: usize = 100_000;
+/// Bytes of CSV collected before writing
+const CSV_BUFFER: usize = 1000;
+/// zstd compression level, fast enough for live recording
+const ZSTD_LEVEL: i32 = 3;
+/// Header row of wide CSV
+pub const WIDE_HEADER: &str = "time,dev,sensory,ch0,ch1,ch2,ch3,ch4,ch5,ch6,ch7,dev_time,arrival";
+
+const PARQUET_SCHEMA: &str = "
+message yld {
+    REQUIRED DOUBLE time;
+    REQUIRED INT32 dev (INTEGER(8, false));
+    REQUIRED INT32 sensory (INTEGER(8, false));
+    REQUIRED INT32 chan (INTEGER(8, false));
+    REQUIRED DOUBLE value;
+    REQUIRED DOUBLE dev_time;
+    REQUIRED DOUBLE arrival;
+}";
+
+/// File format of a recording
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+    #[default]
+    Yld,
+    Wide,
+    Parquet,
+}
+
+pub const FORMATS: [Format; 3] = [Format::Yld, Format::Wide, Format::Parquet];
+
+impl Format {
+    /// File extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Yld => "yld",
+            Format::Wide => "csv",
+            Format::Parquet => "parquet",
+        }
+    }
+
+    /// Format by file extension, Yld for unknown extensions
+    ///
+    /// The extension of a compressed file is the one before `.gz` or `.zst`.
+    pub fn from_path(path: &Path) -> Format {
+        match uncompressed(path).extension() {
+            Some(ext) if ext == "parquet" => Format::Parquet,
+            Some(ext) if ext == "csv" => Format::Wide,
+            _ => Format::Yld,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Yld => write!(f, "Yld"),
+            Format::Wide => write!(f, "Wide CSV"),
+            Format::Parquet => write!(f, "Parquet"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    /// By extension, or "wide" for wide CSV
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("wide") {
+            return Ok(Format::Wide);
+        }
+        FORMATS
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(s.trim()))
+            .ok_or(format!("unknown format: {}", s))
+    }
+}
+
+/// Compression of CSV recordings
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+pub const COMPRESSIONS: [Compression; 3] =
+    [Compression::None, Compression::Gzip, Compression::Zstd];
+
+impl Compression {
+    /// File extension, without the dot, None for uncompressed files
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Compression by file extension
+    pub fn from_path(path: &Path) -> Compression {
+        match path.extension() {
+            Some(ext) if ext == "gz" => Compression::Gzip,
+            Some(ext) if ext == "zst" => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    /// By name or extension
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        COMPRESSIONS
+            .into_iter()
+            .find(|compression| {
+                compression.to_string().eq_ignore_ascii_case(s)
+                    || compression
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case(s))
+            })
+            .ok_or(format!("unknown compression: {}", s))
+    }
+}
+
+/// Path of a file without its compression extension
+pub fn uncompressed(path: &Path) -> PathBuf {
+    match Compression::from_path(path) {
+        Compression::None => path.to_path_buf(),
+        _ => path.with_extension(""),
+    }
+}
+
+/// Name of a recording without extensions, e.g. `p01` for `p01.yld.zst`
+/// or for the manifest `p01.manifest.json` of a segmented recording
+///
+/// Sidecars, markers and exports are named after it.
+pub fn stem(recording: &Path) -> String {
+    let name = recording.file_name().unwrap_or_default().to_string_lossy();
+    if let Some(stem) = name.strip_suffix(segments::MANIFEST) {
+        return stem.to_string();
+    }
+    let path = uncompressed(recording);
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Path of a recording with the compression extension added, if missing
+///
+/// Parquet is never compressed, its path stays as it is.
+pub fn compressed_path(path: PathBuf, format: Format, compression: Compression) -> PathBuf {
+    match compression.extension() {
+        Some(ext) if format != Format::Parquet && Compression::from_path(&path) != compression => {
+            let mut name = path.file_name().unwrap_or_default().to_os_string();
+            name.push(".");
+            name.push(ext);
+            path.with_file_name(name)
+        }
+        _ => path,
+    }
+}
+
+/// File of a CSV sink, compressed or not
+enum Output {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl Output {
+    fn create(path: &Path, compression: Compression) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(match compression {
+            Compression::None => Output::Plain(file),
+            Compression::Gzip => Output::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => Output::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?),
+        })
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Output::Plain(file) => file.write_all(buf),
+            Output::Gzip(encoder) => encoder.write_all(buf),
+            Output::Zstd(encoder) => encoder.write_all(buf),
+        }
+    }
+
+    /// Making everything written so far readable from the file
+    ///
+    /// Compressors end their current block, which costs a little compression.
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(file) => file.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            Output::Zstd(encoder) => encoder.flush(),
+        }
+    }
+
+    /// Flushing, then waiting until the file is on disk
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match self {
+            Output::Plain(file) => file.sync_data(),
+            Output::Gzip(encoder) => encoder.get_ref().sync_data(),
+            Output::Zstd(encoder) => encoder.get_ref().sync_data(),
+        }
+    }
+
+    /// Ending the compressed stream, then waiting until the file is on disk
+    fn finish(self) -> io::Result<()> {
+        let file = match self {
+            Output::Plain(file) => file,
+            Output::Gzip(encoder) => encoder.finish()?,
+            Output::Zstd(encoder) => encoder.finish()?,
+        };
+        file.sync_all()
+    }
+}
+
+/// Writing a whole file at once, e.g. a repaired recording (see `recovery`)
+pub(crate) fn write_file(path: &Path, compression: Compression, data: &[u8]) -> io::Result<()> {
+    let mut output = Output::create(path, compression)?;
+    output.write_all(data)?;
+    output.finish()
+}
+
+/// Where the rows of a recording go
+pub trait Sink: Send {
+    fn write(&mut self, yld: &Yld) -> io::Result<()>;
+    /// Writing out what is buffered, so it survives a crash of Ystudio
+    fn flush(&mut self) -> io::Result<()>;
+    /// Flushing and waiting until it is on disk, so it survives a power cut
+    fn sync(&mut self) -> io::Result<()>;
+    /// Writing out the rest and closing, with the final metadata, on disk
+    fn finish(self: Box<Self>, meta: &Meta) -> io::Result<()>;
+}
+
+/// Creating the file of a recording
+///
+/// `compression` applies to Yld and wide CSV, Parquet is compressed anyway.
+pub fn create(path: &Path, format: Format, compression: Compression) -> io::Result<Box<dyn Sink>> {
+    Ok(match format {
+        Format::Yld => Box::new(YldSink::create(path, compression)?),
+        Format::Wide => Box::new(WideSink::create(path, compression)?),
+        Format::Parquet => Box::new(ParquetSink::create(path)?),
+    })
+}
+
+/// Long CSV, as written by `Yld::to_csv_line`
+pub struct YldSink {
+    output: Output,
+    buffer: String,
+}
+
+impl YldSink {
+    pub fn create(path: &Path, compression: Compression) -> io::Result<Self> {
+        Ok(YldSink {
+            output: Output::create(path, compression)?,
+            buffer: String::new(),
+        })
+    }
+}
+
+impl Sink for YldSink {
+    fn write(&mut self, yld: &Yld) -> io::Result<()> {
+        self.buffer.push_str(&yld.to_csv_line());
+        if self.buffer.len() > CSV_BUFFER {
+            // into the compressor, which collects its blocks
+            self.output.write_all(self.buffer.as_bytes())?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.write_all(self.buffer.as_bytes())?;
+        self.buffer.clear();
+        self.output.flush()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.output.write_all(self.buffer.as_bytes())?;
+        self.buffer.clear();
+        self.output.sync()
+    }
+
+    fn finish(mut self: Box<Self>, _meta: &Meta) -> io::Result<()> {
+        self.output.write_all(self.buffer.as_bytes())?;
+        self.output.finish()
+    }
+}
+
+/// Wide CSV, one line per sample
+///
+/// The rows of a sample arrive one after the other (see `Ytf8::to_yld`),
+/// they are collected until the next sample starts.
+pub struct WideSink {
+    output: Output,
+    buffer: String,
+    sample: Option<(Yld, [Option<f64>; 8])>, // first row and values of the current sample
+}
+
+impl WideSink {
+    pub fn create(path: &Path, compression: Compression) -> io::Result<Self> {
+        Ok(WideSink {
+            output: Output::create(path, compression)?,
+            buffer: format!("{}\r\n", WIDE_HEADER),
+            sample: None,
+        })
+    }
+
+    /// Adding the current sample to the buffer
+    fn push_sample(&mut self) {
+        if let Some((first, values)) = self.sample.take() {
+            let mut line = format!(
+                "{},{},{}",
+                first.time.as_secs_f64(),
+                first.dev,
+                first.sensory
+            );
+            // channels not recorded, e.g. when recording started mid-sample, stay empty
+            for value in values {
+                line.push(',');
+                if let Some(value) = value {
+                    line.push_str(&value.to_string());
+                }
+            }
+            line.push_str(&format!(
+                ",{},{}\r\n",
+                first.dev_time.as_secs_f64(),
+                first.arrival.as_secs_f64()
+            ));
+            self.buffer.push_str(&line);
+        }
+    }
+}
+
+impl Sink for WideSink {
+    fn write(&mut self, yld: &Yld) -> io::Result<()> {
+        if yld.chan >= 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("channel {} out of range", yld.chan),
+            ));
+        }
+        let same = self.sample.as_ref().is_some_and(|(first, _)| {
+            first.time == yld.time && first.dev == yld.dev && first.sensory == yld.sensory
+        });
+        if !same {
+            self.push_sample();
+            self.sample = Some((*yld, [None; 8]));
+        }
+        if let Some((_, values)) = self.sample.as_mut() {
+            values[yld.chan as usize] = Some(yld.value);
+        }
+        if self.buffer.len() > CSV_BUFFER {
+            self.output.write_all(self.buffer.as_bytes())?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    /// Writing out complete samples
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.write_all(self.buffer.as_bytes())?;
+        self.buffer.clear();
+        self.output.flush()
+    }
+
+    /// Syncing complete samples
+    fn sync(&mut self) -> io::Result<()> {
+        self.output.write_all(self.buffer.as_bytes())?;
+        self.buffer.clear();
+        self.output.sync()
+    }
+
+    fn finish(mut self: Box<Self>, _meta: &Meta) -> io::Result<()> {
+        self.push_sample();
+        self.output.write_all(self.buffer.as_bytes())?;
+        self.output.finish()
+    }
+}
+
+/// A time in seconds, None if negative, not a number or too large
+fn secs(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+/// Reading the rows of a sample back from a line of wide CSV
+///
+/// Empty channels give no rows.
+/// Returns None for the header and lines that can not be read.
+pub fn from_wide_line(line: &str) -> Option<Vec<Yld>> {
+    // None for empty cells
+    let cols: Vec<Option<f64>> = line
+        .trim()
+        .split(',')
+        .map(|col| match col.trim() {
+            "" => Ok(None),
+            col => col.parse::<f64>().map(Some),
+        })
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if cols.len() != 13 {
+        return None;
+    }
+    let (time, dev_time, arrival) = (secs(cols[0]?)?, secs(cols[11]?)?, secs(cols[12]?)?);
+    let (dev, sensory) = (cols[1]?, cols[2]?);
+    let rows = (0..8)
+        .filter_map(|chan| {
+            Some(Yld {
+                time,
+                dev: dev as u8,
+                sensory: sensory as u8,
+                chan,
+                value: cols[3 + chan as usize]?,
+                dev_time,
+                arrival,
+            })
+        })
+        .collect();
+    Some(rows)
+}
+
+/// Parquet with typed columns, one row group per `ROW_GROUP` rows
+pub struct ParquetSink {
+    writer: SerializedFileWriter<File>,
+    time: Vec<f64>,
+    dev: Vec<i32>,
+    sensory: Vec<i32>,
+    chan: Vec<i32>,
+    value: Vec<f64>,
+    dev_time: Vec<f64>,
+    arrival: Vec<f64>,
+}
+
+impl ParquetSink {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+        let props = WriterProperties::builder()
+            .set_compression(parquet::basic::Compression::SNAPPY)
+            .set_created_by(format!("Ystudio {}", env!("CARGO_PKG_VERSION")))
+            .build();
+        Ok(ParquetSink {
+            writer: SerializedFileWriter::new(File::create(path)?, schema, Arc::new(props))?,
+            time: Vec::new(),
+            dev: Vec::new(),
+            sensory: Vec::new(),
+            chan: Vec::new(),
+            value: Vec::new(),
+            dev_time: Vec::new(),
+            arrival: Vec::new(),
+        })
+    }
+}
+
+impl ParquetSink {
+    /// Writing the buffered rows as a row group
+    fn write_row_group(&mut self) -> io::Result<()> {
+        if self.time.is_empty() {
+            return Ok(());
+        }
+        let mut group = self.writer.next_row_group()?;
+        // columns in the order of the schema
+        write_column::<DoubleType>(&mut group, &self.time)?;
+        write_column::<Int32Type>(&mut group, &self.dev)?;
+        write_column::<Int32Type>(&mut group, &self.sensory)?;
+        write_column::<Int32Type>(&mut group, &self.chan)?;
+        write_column::<DoubleType>(&mut group, &self.value)?;
+        write_column::<DoubleType>(&mut group, &self.dev_time)?;
+        write_column::<DoubleType>(&mut group, &self.arrival)?;
+        group.close()?;
+        self.time.clear();
+        self.dev.clear();
+        self.sensory.clear();
+        self.chan.clear();
+        self.value.clear();
+        self.dev_time.clear();
+        self.arrival.clear();
+        Ok(())
+    }
+}
+
+impl Sink for ParquetSink {
+    fn write(&mut self, yld: &Yld) -> io::Result<()> {
+        self.time.push(yld.time.as_secs_f64());
+        self.dev.push(yld.dev as i32);
+        self.sensory.push(yld.sensory as i32);
+        self.chan.push(yld.chan as i32);
+        self.value.push(yld.value);
+        self.dev_time.push(yld.dev_time.as_secs_f64());
+        self.arrival.push(yld.arrival.as_secs_f64());
+        if self.time.len() >= ROW_GROUP {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    /// Nothing to do: a Parquet file can only be read once it is finished,
+    /// and row groups are written when full, not to make them small
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writing the buffered rows as a row group, then syncing
+    ///
+    /// Closing the columns of a row group flushes the writer's own buffer,
+    /// so all that was written reaches the file before it is synced.
+    fn sync(&mut self) -> io::Result<()> {
+        self.write_row_group()?;
+        self.writer.inner().sync_data()
+    }
+
+    fn finish(mut self: Box<Self>, meta: &Meta) -> io::Result<()> {
+        self.write_row_group()?;
+        self.writer.append_key_value_metadata(KeyValue::new(
+            META_KEY.to_string(),
+            serde_json::to_string(meta)?,
+        ));
+        // writes the footer
+        self.writer.into_inner()?.sync_all()
+    }
+}
+
+/// Writing the next column of a row group
+fn write_column<T: parquet::data_type::DataType>(
+    group: &mut parquet::file::writer::SerializedRowGroupWriter<File>,
+    values: &[T::T],
+) -> io::Result<()> {
+    let mut column = group
+        .next_column()?
+        .ok_or(io::Error::other("more columns than in the schema"))?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()?;
+    Ok(())
+}
+
+/// Reading all rows of a Parquet recording, without those that can not be read
+pub fn read_parquet(path: &Path) -> io::Result<Vec<Yld>> {
+    let mut out = Vec::new();
+    for_each_parquet_row(path, |yld| {
+        out.push(yld);
+        Ok(())
+    })?;
+    Ok(out)
+}
+
+/// Passing the rows of a Parquet recording on, one by one
+///
+/// Rows with times out of range (e.g. negative) or a channel
+/// out of range are skipped. Returns the number of skipped rows.
+pub fn for_each_parquet_row(
+    path: &Path,
+    mut f: impl FnMut(Yld) -> io::Result<()>,
+) -> io::Result<usize> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let mut skipped = 0;
+    for row in reader.get_row_iter(None)? {
+        let row = row?;
+        let times = (
+            secs(row.get_double(0)?),
+            secs(row.get_double(5)?),
+            secs(row.get_double(6)?),
+        );
+        let chan = row.get_ubyte(3)?;
+        match times {
+            (Some(time), Some(dev_time), Some(arrival)) if chan < 8 => f(Yld {
+                time,
+                dev: row.get_ubyte(1)?,
+                sensory: row.get_ubyte(2)?,
+                chan,
+                value: row.get_double(4)?,
+                dev_time,
+                arrival,
+            })?,
+            _ => skipped += 1,
+        }
+    }
+    Ok(skipped)
+}
+
+/// Has a Parquet file been finished?
+///
+/// Only finished files have a footer and can be read.
+pub fn is_finished_parquet(path: &Path) -> bool {
+    File::open(path).is_ok_and(|file| SerializedFileReader::new(file).is_ok())
+}
+
+/// Reading the recording metadata of a Parquet file
+pub fn read_parquet_meta(path: &Path) -> io::Result<Option<Meta>> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let json = reader
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kvs| kvs.iter().find(|kv| kv.key == META_KEY))
+        .and_then(|kv| kv.value.clone());
+    match json {
+        None => Ok(None),
+        Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+    }
+}
+
+/// Converting a recording into another format, e.g. wide CSV into Yld
+///
+/// Also compresses or decompresses recordings, e.g. Yld into Yld with zstd.
+/// The metadata is taken from the sidecar of the recording, if there is one.
+/// Converting into the recording itself (or one of its segments) is refused.
+/// Returns the number of rows written and of lines that could not be read.
+pub fn convert(
+    from: &Path,
+    to: &Path,
+    format: Format,
+    compression: Compression,
+) -> io::Result<(u64, usize)> {
+    let same = Format::from_path(from) == format && Compression::from_path(from) == compression;
+    if same && !segments::is_manifest(from) {
+        return Err(io::Error::other(format!(
+            "{:?} is {} already",
+            from, format
+        )));
+    }
+    // creating `to` would truncate what is read
+    let mut inputs = vec![from.to_path_buf()];
+    if segments::is_manifest(from) {
+        inputs.extend(segments::Manifest::read(from)?.paths(from));
+    }
+    if let Ok(to_file) = to.canonicalize() {
+        if inputs
+            .iter()
+            .any(|input| input.canonicalize().is_ok_and(|input| input == to_file))
+        {
+            return Err(io::Error::other(format!("{:?} is read for converting", to)));
+        }
+    }
+    let mut sink = create(to, format, compression)?;
+    let mut rows = 0;
+    let skipped = for_each_row(from, |yld| {
+        rows += 1;
+        sink.write(&yld)
+    })?;
+    sink.finish(&Meta::read(from).unwrap_or_default())?;
+    Ok((rows, skipped))
+}
+
+/// Path of a converted file, next to the original
+pub fn converted_path(from: &Path, format: Format, compression: Compression) -> PathBuf {
+    let path = from.with_file_name(format!("{}.{}", stem(from), format.extension()));
+    compressed_path(path, format, compression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_lines_out_of_range_are_not_read() {
+        let line = "1.5,0,1,1,2,3,4,5,6,7,8,1.4,1.6";
+        let rows = from_wide_line(line).unwrap();
+        assert_eq!(rows.len(), 8);
+        assert_eq!((rows[7].chan, rows[7].value), (7, 8.0));
+        assert_eq!(rows[0].dev_time, Duration::from_secs_f64(1.4));
+        for time in ["-1.5", "NaN", "inf", "1e300"] {
+            assert!(
+                from_wide_line(&line.replacen("1.5", time, 1)).is_none(),
+                "{}",
+                time
+            );
+        }
+        assert!(from_wide_line(&line.replace("1.6", "inf")).is_none());
+        assert!(from_wide_line("time,dev,sensory").is_none());
+        assert!(from_wide_line(",0,1,1,2,3,4,5,6,7,8,1.4,1.6").is_none());
+    }
+
+    #[test]
+    fn partial_samples_leave_cells_empty() {
+        let dir = std::env::temp_dir().join(format!("ystudio-wide-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recording.csv");
+        let row = |time: u64, chan: u8| Yld {
+            time: Duration::from_millis(time),
+            dev: 0,
+            sensory: 1,
+            chan,
+            value: chan as f64 + 0.5,
+            dev_time: Duration::from_millis(time),
+            arrival: Duration::from_millis(time),
+        };
+        let mut sink = Box::new(WideSink::create(&path, Compression::None).unwrap());
+        // starting and stopping mid-sample
+        for (time, chan) in [(1000, 6), (1000, 7), (1002, 0), (1002, 1)] {
+            sink.write(&row(time, chan)).unwrap();
+        }
+        assert!(sink.write(&row(1002, 8)).is_err());
+        sink.finish(&Meta::default()).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "1,0,1,,,,,,,6.5,7.5,1,1");
+        assert_eq!(lines[2], "1.002,0,1,0.5,1.5,,,,,,,1.002,1.002");
+        // read back as the rows written
+        let chans: Vec<u8> = lines[1..]
+            .iter()
+            .flat_map(|line| from_wide_line(line).unwrap())
+            .map(|yld| yld.chan)
+            .collect();
+        assert_eq!(chans, [6, 7, 0, 1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn converting_into_the_recording_is_refused() {
+        let dir = std::env::temp_dir().join(format!("ystudio-sinks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("recording.yld");
+        std::fs::write(&recording, "1.0,0,1,0,5\n").unwrap();
+        let same = dir.join(".").join("recording.yld");
+        assert!(convert(&recording, &same, Format::Parquet, Compression::None).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&recording).unwrap(),
+            "1.0,0,1,0,5\n"
+        );
+
+        let parquet = dir.join("recording.parquet");
+        assert_eq!(
+            convert(&recording, &parquet, Format::Parquet, Compression::None).unwrap(),
+            (1, 0)
+        );
+        assert_eq!(read_parquet(&parquet).unwrap().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+/// Data sources
+///
+/// A data source yields Ytf8 samples to the YLab thread.
+/// The thread connects, reads and disconnects the same way
+/// for every source, so new inputs only implement `DataSource`.
+///
+/// + `StreamSource` over a serial port (a YLab) or TCP (a networked YLab),
+///   taking binary Ytf frames as well as CSV lines
+/// + `Replay` of recorded files (see replay.rs)
+/// + `YSim`, the built-in simulator (see ysim.rs)
+use crate::frame::{Decoded, FrameDecoder};
+use crate::ylab::data::Ytf8;
+use crate::ylab::YLabVersion;
+use std::fmt;
+use std::io::{self, Read};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Result of asking a source for the next sample
+#[derive(Debug, Clone)]
+pub enum Reading {
+    /// a complete sample
+    Sample(Ytf8),
+    /// nothing available right now, ask again later
+    Empty,
+    /// data arrived, but it was not a sample
+    Invalid(String),
+    /// the source has ended (end of file, connection closed)
+    Closed,
+    /// the source failed, e.g. the device was unplugged
+    Lost(String),
+}
+
+/// A source of Ytf8 samples
+///
+/// `next_sample` must not block for long (a few ms at most),
+/// because the YLab thread also listens for commands in between.
+pub trait DataSource: Send {
+    /// Name shown as port name while connected
+    fn name(&self) -> String;
+    /// The next sample, if one is available
+    fn next_sample(&mut self) -> Reading;
+}
+
+impl fmt::Debug for dyn DataSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DataSource({})", self.name())
+    }
+}
+
+/// Prefix of port names that connect over TCP, e.g. "tcp://192.168.0.10:5000"
+pub const TCP_PREFIX: &str = "tcp://";
+
+/// Ytf frames and Ytf8 CSV lines from any byte stream
+///
+/// Bytes are collected until a frame or line is complete,
+/// so partial lines (e.g. on a read timeout) are kept.
+pub struct StreamSource<R: Read + Send> {
+    name: String,
+    reader: R,
+    decoder: FrameDecoder,
+    closed: bool,
+    error: Option<String>, // why reading failed
+}
+
+pub type SerialSource = StreamSource<Box<dyn serialport::SerialPort>>;
+pub type TcpSource = StreamSource<TcpStream>;
+
+impl<R: Read + Send> StreamSource<R> {
+    pub fn new(name: String, reader: R) -> Self {
+        StreamSource {
+            name,
+            reader,
+            decoder: FrameDecoder::new(),
+            closed: false,
+            error: None,
+        }
+    }
+
+    /// Reading what has arrived into the decoder
+    fn fill(&mut self) {
+        let mut bytes = [0u8; 4096];
+        match self.reader.read(&mut bytes) {
+            // end of stream
+            Ok(0) => self.closed = true,
+            Ok(n) => self.decoder.push(&bytes[..n]),
+            // read timeout, nothing arrived
+            Err(e)
+                if e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                self.closed = true;
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+impl SerialSource {
+    /// Opening a serial port at the baud rate of the YLab version
+    pub fn open(port_name: &str, version: YLabVersion) -> serialport::Result<Self> {
+        Self::open_at(port_name, version.baud())
+    }
+
+    /// Opening a serial port at a given baud rate
+    pub fn open_at(port_name: &str, baud: u32) -> serialport::Result<Self> {
+        let port = serialport::new(port_name, baud)
+            .timeout(Duration::from_millis(1))
+            .flow_control(serialport::FlowControl::Software)
+            .open()?;
+        Ok(Self::new(port_name.to_string(), port))
+    }
+}
+
+impl TcpSource {
+    /// Connecting to a YLab that sends over TCP
+    ///
+    /// `address` is host:port, with or without the "tcp://" prefix
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let address = address.strip_prefix(TCP_PREFIX).unwrap_or(address);
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(Duration::from_millis(1)))?;
+        Ok(Self::new(format!("{}{}", TCP_PREFIX, address), stream))
+    }
+}
+
+impl<R: Read + Send> DataSource for StreamSource<R> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn next_sample(&mut self) -> Reading {
+        let mut decoded = self.decoder.next();
+        if decoded.is_none() && !self.closed {
+            self.fill();
+            decoded = self.decoder.next();
+        }
+        if decoded.is_none() && self.closed {
+            decoded = self.decoder.finish();
+        }
+        match decoded {
+            Some(Decoded::Frame(sample)) => Reading::Sample(sample),
+            // CSV fallback
+            Some(Decoded::Line(line)) => match Ytf8::from_csv_line(&line) {
+                Ok(sample) => Reading::Sample(sample),
+                Err(e) => Reading::Invalid(format!("Not Ytf8: {:?}", e)),
+            },
+            Some(Decoded::Skipped(n)) => Reading::Invalid(format!("{} bytes out of sync", n)),
+            None if self.closed => match self.error.as_ref() {
+                Some(e) => Reading::Lost(e.clone()),
+                None => Reading::Closed,
+            },
+            None => Reading::Empty,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+/// Live data stream
+///
+/// Publishes every sample to other programs while Ystudio keeps
+/// recording, e.g. for closed-loop scripts in Python or R.
+/// Clients connect to localhost over TCP (port 6531 by default)
+/// and receive one JSON object per line and sample:
+///
+/// `{"time":12.345,"dev_time":10.2,"arrival":12.347,"dev":1,"sensory":1,"bank":"ADC","version":"Go","read":[512.0,498.0,530.0,0.0,0.0,0.0,0.0,0.0]}`
+///
+/// Times are in seconds, `time` is the device time on the Ystudio clock
+/// (as in Yld files). Clients that do not keep up lose samples,
+/// rather than slowing down reading and recording.
+use crate::ylab::data::Ytf8;
+use crate::ylab::YLabVersion;
+use serde::Serialize;
+use std::io::{self, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Default port for the live stream
+pub const STREAM_PORT: u16 = 6531;
+/// Lines queued per client before samples are dropped
+const BACKLOG: usize = 10_000;
+
+/// A sample as published
+#[derive(Serialize)]
+struct StreamSample<'a> {
+    time: f64,
+    dev_time: f64,
+    arrival: f64,
+    dev: u8,
+    sensory: u8,
+    bank: &'a str,
+    version: String,
+    read: [f64; 8],
+}
+
+/// Sending lines to all subscribers
+#[derive(Clone, Default)]
+pub struct Broadcast {
+    subscribers: Arc<Mutex<Vec<SyncSender<String>>>>,
+}
+
+impl Broadcast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new subscriber, receiving all lines published from now on
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (send, receive) = mpsc::sync_channel(BACKLOG);
+        self.subscribers.lock().unwrap().push(send);
+        receive
+    }
+
+    /// Publishing a line to all subscribers
+    ///
+    /// Subscribers that have gone are removed,
+    /// slow ones miss the line.
+    pub fn publish(&self, line: String) {
+        self.subscribers.lock().unwrap().retain(|send| {
+            !matches!(
+                send.try_send(line.clone()),
+                Err(TrySendError::Disconnected(_))
+            )
+        });
+    }
+
+    /// Publishing a sample with its times, as JSON
+    pub fn publish_sample(
+        &self,
+        sample: &Ytf8,
+        time: Duration,
+        arrival: Duration,
+        version: YLabVersion,
+    ) {
+        if self.subscribers.lock().unwrap().is_empty() {
+            return;
+        }
+        let labels = version.bank_labels();
+        let json = serde_json::to_string(&StreamSample {
+            time: time.as_secs_f64(),
+            dev_time: sample.time.as_secs_f64(),
+            arrival: arrival.as_secs_f64(),
+            dev: sample.dev,
+            sensory: sample.sensory,
+            bank: labels.get(sample.sensory as usize).copied().unwrap_or(""),
+            version: version.to_string(),
+            read: sample.read,
+        });
+        if let Ok(json) = json {
+            self.publish(json);
+        }
+    }
+}
+
+/// Serving the stream on localhost
+///
+/// Returns once the port is bound, serving goes on in threads.
+pub fn serve_stream(port: u16, broadcast: Broadcast) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let lines = broadcast.subscribe();
+            thread::spawn(move || client_thread(stream, lines));
+        }
+    });
+    Ok(())
+}
+
+/// Writing lines to one client, until it disconnects
+fn client_thread(stream: TcpStream, lines: Receiver<String>) {
+    let mut writer = BufWriter::new(stream);
+    while let Ok(line) = lines.recv() {
+        // writing what is queued in one go
+        let queued = std::iter::once(line).chain(lines.try_iter());
+        for line in queued {
+            if writeln!(writer, "{}", line).is_err() {
+                return;
+            }
+        }
+        if writer.flush().is_err() {
+            return;
+        }
+    }
+}
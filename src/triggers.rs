@@ -0,0 +1,69 @@
+/// External triggers
+///
+/// Stimulus software on the same computer (PsychoPy, Unity etc.)
+/// sends event codes to Ystudio, which sets them as markers,
+/// on the same clock as the data.
+///
+/// Ystudio listens on localhost, on the same port for UDP and TCP:
+/// + UDP: every datagram holds one or more lines, one label per line
+/// + TCP: one label per line; every marker is answered with
+///   "ok <time in seconds>", which can be ignored
+///
+/// For example, from Python:
+/// `socket.socket(socket.AF_INET, socket.SOCK_DGRAM).sendto(b"stim_on", ("127.0.0.1", 6530))`
+use crate::marks::Marks;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::thread;
+
+/// Default port for triggers
+pub const TRIGGER_PORT: u16 = 6530;
+
+/// Starting the trigger listeners on localhost
+///
+/// Returns once both sockets are bound, listening goes on in threads.
+pub fn listen_triggers(port: u16, marks: Marks) -> io::Result<()> {
+    let udp = UdpSocket::bind(("127.0.0.1", port))?;
+    let tcp = TcpListener::bind(("127.0.0.1", port))?;
+    let udp_marks = marks.clone();
+    thread::spawn(move || udp_thread(udp, udp_marks));
+    thread::spawn(move || {
+        for stream in tcp.incoming().map_while(Result::ok) {
+            let marks = marks.clone();
+            thread::spawn(move || tcp_thread(stream, marks));
+        }
+    });
+    Ok(())
+}
+
+/// Marker labels in a message, one per line, empty lines ignored
+fn labels(message: &str) -> impl Iterator<Item = &str> {
+    message.lines().map(str::trim).filter(|l| !l.is_empty())
+}
+
+fn udp_thread(socket: UdpSocket, marks: Marks) {
+    let mut buf = [0u8; 1500];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Err(e) => eprintln!("Trigger: {}", e),
+            Ok((n, _)) => {
+                for label in labels(&String::from_utf8_lossy(&buf[..n])) {
+                    marks.mark(label);
+                }
+            }
+        }
+    }
+}
+
+fn tcp_thread(stream: TcpStream, marks: Marks) {
+    let Ok(mut reply) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        for label in labels(&line) {
+            let mark = marks.mark(label);
+            // the client may not read answers, so errors are ignored
+            let _ = writeln!(reply, "ok {}", mark.time.as_secs_f64());
+        }
+    }
+}
@@ -0,0 +1,504 @@
+/// XDF export
+///
+/// XDF is the file format of Lab Streaming Layer (LSL), written by
+/// LabRecorder and read with pyxdf or the EEGLAB/MNE importers.
+/// A recording is exported with one stream per sensory bank, stamped with
+/// device time (the stream's own clock), and clock offset chunks that map
+/// device time onto host time, as LabRecorder does for LSL streams.
+/// Markers go into a string stream of their own, on host time.
+/// Values of calibrated sensories are physical (e.g. mV for ADC).
+///
+/// Host time is the LSL clock when the metadata of the recording has it
+/// (`lsl_clock`), so exports line up with LabRecorder files of the same
+/// session after clock synchronisation (`pyxdf.load_xdf(..., synchronize_clocks=True)`).
+/// Otherwise it is the Ystudio clock: the LSL clock is only noted when
+/// recording on Linux (see `clock::lsl_clock_start`), `export` tells which.
+///
+/// Rows are read one by one and written in chunks as they come,
+/// so chunks of the streams are interleaved, as in LabRecorder files.
+use crate::marks::Mark;
+use crate::meta::{self, Meta};
+use crate::sinks;
+use crate::ylab::data::Yld;
+use crate::ylab::{Calibration, Sensory, YLabVersion};
+use crate::yldfile::{for_each_row, read_marks};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Samples per chunk
+const CHUNK: usize = 1000;
+/// Device time between clock offsets, in seconds
+const OFFSET_SECS: f64 = 5.0;
+
+/// Chunk tags
+const FILE_HEADER: u16 = 1;
+const STREAM_HEADER: u16 = 2;
+const SAMPLES: u16 = 3;
+const CLOCK_OFFSET: u16 = 4;
+const STREAM_FOOTER: u16 = 6;
+
+/// A sample of a bank: device time, host time and channel values
+struct Sample {
+    dev_time: f64,
+    time: f64,
+    values: Vec<f64>,
+}
+
+/// Exporting a recording (Yld or Parquet) with its markers
+///
+/// The YLab version is taken from the metadata of the recording,
+/// `version` is used for recordings without.
+/// Returns the number of streams written and if host time is the LSL clock.
+pub fn export(
+    recording: &Path,
+    out: &Path,
+    version: Option<YLabVersion>,
+) -> io::Result<(usize, bool)> {
+    let meta = Meta::read(recording).ok();
+    let version = meta
+        .as_ref()
+        .and_then(|meta| meta.device.as_ref())
+        .and_then(|device| device.version.parse::<YLabVersion>().ok())
+        .or(version)
+        .ok_or(io::Error::other("YLab version unknown, please give one"))?;
+    let lsl_clock = meta.as_ref().and_then(|meta| meta.lsl_clock);
+    let on_lsl = lsl_clock.is_some();
+    let lsl_clock = lsl_clock.unwrap_or(0.0);
+    let labels = version.bank_labels();
+    let sensories = version.sensories();
+
+    let mut file = BufWriter::new(File::create(out)?);
+    file.write_all(b"XDF:")?;
+    chunk(
+        &mut file,
+        FILE_HEADER,
+        b"<?xml version=\"1.0\"?><info><version>1.0</version></info>",
+    )?;
+    // streams of the banks, by bank, begun at their first row
+    let mut streams: BTreeMap<u8, BankStream> = BTreeMap::new();
+    let mut stream_id = 0;
+    for_each_row(recording, |row| {
+        let stream = match streams.get_mut(&row.sensory) {
+            Some(stream) => stream,
+            None => {
+                let Some(sensory) = sensories.get(row.sensory as usize).copied() else {
+                    return Ok(());
+                };
+                stream_id += 1;
+                let label = labels[row.sensory as usize];
+                let header = bank_header(version, label, sensory, meta.as_ref());
+                write_stream_header(&mut file, stream_id, &header)?;
+                streams.entry(row.sensory).or_insert(BankStream::new(stream_id, sensory))
+            }
+        };
+        stream.add(&mut file, &row, lsl_clock)
+    })?;
+    for stream in streams.into_values() {
+        stream.finish(&mut file)?;
+    }
+
+    let marks = read_marks(recording);
+    if !marks.is_empty() {
+        stream_id += 1;
+        write_stream_header(&mut file, stream_id, &marks_header(meta.as_ref()))?;
+        for marks in marks.chunks(CHUNK) {
+            let mut content = stream_id.to_le_bytes().to_vec();
+            push_varlen(&mut content, marks.len() as u64);
+            for Mark { time, label } in marks {
+                push_time(&mut content, time.as_secs_f64() + lsl_clock);
+                push_varlen(&mut content, label.len() as u64);
+                content.extend(label.as_bytes());
+            }
+            chunk(&mut file, SAMPLES, &content)?;
+        }
+        let first = marks[0].time.as_secs_f64() + lsl_clock;
+        let last = marks[marks.len() - 1].time.as_secs_f64() + lsl_clock;
+        write_stream_footer(&mut file, stream_id, first, last, marks.len(), &[])?;
+    }
+    file.flush()?;
+    Ok((stream_id as usize, on_lsl))
+}
+
+/// Path of an export, next to the recording
+pub fn export_path(recording: &Path) -> PathBuf {
+    recording.with_file_name(format!("{}.xdf", sinks::stem(recording)))
+}
+
+/// The stream of a bank while it is written
+///
+/// Rows are regrouped into samples, and samples written in chunks of `CHUNK`.
+/// Clock offsets are collected and written at the end, with the footer.
+struct BankStream {
+    id: u32,
+    n_chan: usize,
+    calibration: Option<Calibration>,
+    current: Option<(Duration, u8, Sample)>, // time and dev of the sample being filled
+    chunk: Vec<Sample>,
+    offsets: Vec<(f64, f64)>,
+    first: f64, // device times
+    last: f64,
+    count: usize,
+}
+
+impl BankStream {
+    fn new(id: u32, sensory: Sensory) -> Self {
+        BankStream {
+            id,
+            n_chan: sensory.n_chan() as usize,
+            calibration: sensory.calibration(),
+            current: None,
+            chunk: vec![],
+            offsets: vec![],
+            first: 0.0,
+            last: 0.0,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, file: &mut impl Write, row: &Yld, lsl_clock: f64) -> io::Result<()> {
+        if !matches!(&self.current, Some((time, dev, _)) if *time == row.time && *dev == row.dev) {
+            let sample = Sample {
+                dev_time: row.dev_time.as_secs_f64(),
+                time: row.time.as_secs_f64() + lsl_clock,
+                values: vec![0.0; self.n_chan],
+            };
+            if let Some((_, _, done)) = self.current.replace((row.time, row.dev, sample)) {
+                self.push(file, done)?;
+            }
+        }
+        if let Some((_, _, sample)) = self.current.as_mut() {
+            if let Some(value) = sample.values.get_mut(row.chan as usize) {
+                *value = match self.calibration {
+                    Some(calibration) => calibration.to_physical(row.value),
+                    None => row.value,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, file: &mut impl Write, sample: Sample) -> io::Result<()> {
+        if self.count == 0 {
+            self.first = sample.dev_time;
+        }
+        self.last = sample.dev_time;
+        self.count += 1;
+        add_offset(&mut self.offsets, &sample);
+        self.chunk.push(sample);
+        if self.chunk.len() >= CHUNK {
+            self.write_chunk(file)?;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, file: &mut impl Write) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let mut content = self.id.to_le_bytes().to_vec();
+        push_varlen(&mut content, self.chunk.len() as u64);
+        for sample in self.chunk.drain(..) {
+            push_time(&mut content, sample.dev_time);
+            for value in &sample.values {
+                content.extend(value.to_le_bytes());
+            }
+        }
+        chunk(file, SAMPLES, &content)
+    }
+
+    /// Writing the last samples, the clock offsets and the footer
+    fn finish(mut self, file: &mut impl Write) -> io::Result<()> {
+        if let Some((_, _, sample)) = self.current.take() {
+            self.push(file, sample)?;
+        }
+        self.write_chunk(file)?;
+        for (collected, offset) in &self.offsets {
+            let mut content = self.id.to_le_bytes().to_vec();
+            content.extend(collected.to_le_bytes());
+            content.extend(offset.to_le_bytes());
+            chunk(file, CLOCK_OFFSET, &content)?;
+        }
+        write_stream_footer(file, self.id, self.first, self.last, self.count, &self.offsets)
+    }
+}
+
+/// Host minus device time, every `OFFSET_SECS` of device time
+///
+/// Host times are the synchronised times of the recording,
+/// so the offsets carry the drift correction of Ystudio.
+fn add_offset(offsets: &mut Vec<(f64, f64)>, sample: &Sample) {
+    let due = match offsets.last() {
+        None => true,
+        // device restarts go back in time
+        Some((collected, _)) => {
+            sample.dev_time >= collected + OFFSET_SECS || sample.dev_time < *collected
+        }
+    };
+    if due {
+        offsets.push((sample.dev_time, sample.time - sample.dev_time));
+    }
+}
+
+/// Escaping text for XML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Stream header of a bank, with channel labels and units
+fn bank_header(
+    version: YLabVersion,
+    label: &str,
+    sensory: Sensory,
+    meta: Option<&Meta>,
+) -> String {
+    let unit = sensory.calibration().map_or("", |calibration| calibration.unit);
+    let channels: String = (0..sensory.n_chan())
+        .map(|chan| {
+            format!(
+                "<channel><label>{} {}</label><unit>{}</unit><type>{}</type></channel>",
+                escape(label),
+                chan,
+                escape(unit),
+                escape(label)
+            )
+        })
+        .collect();
+    let port = meta
+        .and_then(|meta| meta.device.as_ref())
+        .map_or(String::new(), |device| device.port.clone());
+    format!(
+        "<?xml version=\"1.0\"?><info><name>YLab {} {}</name><type>{}</type>\
+         <channel_count>{}</channel_count><nominal_srate>{}</nominal_srate>\
+         <channel_format>double64</channel_format><source_id>{}</source_id>\
+         <desc><manufacturer>YLab</manufacturer><channels>{}</channels>{}</desc></info>",
+        escape(&version.to_string()),
+        escape(label),
+        sensory.kind(),
+        sensory.n_chan(),
+        sensory.hz().unwrap_or(0),
+        escape(&format!("ystudio {} {} {}", version, port, label)),
+        channels,
+        session(meta)
+    )
+}
+
+/// Stream header of the markers
+fn marks_header(meta: Option<&Meta>) -> String {
+    format!(
+        "<?xml version=\"1.0\"?><info><name>Ystudio markers</name><type>Markers</type>\
+         <channel_count>1</channel_count><nominal_srate>0</nominal_srate>\
+         <channel_format>string</channel_format><source_id>ystudio markers</source_id>\
+         <desc>{}</desc></info>",
+        session(meta)
+    )
+}
+
+/// Participant, session and start from the metadata
+fn session(meta: Option<&Meta>) -> String {
+    match meta {
+        None => String::new(),
+        Some(meta) => format!(
+            "<participant>{}</participant><session>{}</session><started>{}</started>",
+            escape(&meta.participant),
+            escape(&meta.session),
+            escape(meta.started.as_deref().unwrap_or_default())
+        ),
+    }
+}
+
+fn write_stream_header(file: &mut impl Write, stream_id: u32, xml: &str) -> io::Result<()> {
+    let mut content = stream_id.to_le_bytes().to_vec();
+    content.extend(xml.as_bytes());
+    chunk(file, STREAM_HEADER, &content)
+}
+
+fn write_stream_footer(
+    file: &mut impl Write,
+    stream_id: u32,
+    first: f64,
+    last: f64,
+    count: usize,
+    offsets: &[(f64, f64)],
+) -> io::Result<()> {
+    let offsets: String = offsets
+        .iter()
+        .map(|(time, value)| format!("<offset><time>{}</time><value>{}</value></offset>", time, value))
+        .collect();
+    let xml = format!(
+        "<?xml version=\"1.0\"?><info><first_timestamp>{}</first_timestamp>\
+         <last_timestamp>{}</last_timestamp><sample_count>{}</sample_count>\
+         <clock_offsets>{}</clock_offsets><exported>{}</exported></info>",
+        first,
+        last,
+        count,
+        offsets,
+        meta::now()
+    );
+    let mut content = stream_id.to_le_bytes().to_vec();
+    content.extend(xml.as_bytes());
+    chunk(file, STREAM_FOOTER, &content)
+}
+
+/// A variable length integer: number of bytes (1, 4 or 8), then the value
+fn push_varlen(out: &mut Vec<u8>, value: u64) {
+    if value <= u8::MAX as u64 {
+        out.push(1);
+        out.push(value as u8);
+    } else if value <= u32::MAX as u64 {
+        out.push(4);
+        out.extend((value as u32).to_le_bytes());
+    } else {
+        out.push(8);
+        out.extend(value.to_le_bytes());
+    }
+}
+
+/// A sample time stamp, always given
+fn push_time(out: &mut Vec<u8>, time: f64) {
+    out.push(8);
+    out.extend(time.to_le_bytes());
+}
+
+/// A chunk: length (of tag and content), tag, content
+fn chunk(file: &mut impl Write, tag: u16, content: &[u8]) -> io::Result<()> {
+    let mut head = vec![];
+    push_varlen(&mut head, content.len() as u64 + 2);
+    head.extend(tag.to_le_bytes());
+    file.write_all(&head)?;
+    file.write_all(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ylab::data::Ytf8;
+
+    /// Splitting a file into chunks of tag and content
+    fn chunks(bytes: &[u8]) -> Vec<(u16, &[u8])> {
+        assert_eq!(&bytes[..4], b"XDF:");
+        let mut chunks = vec![];
+        let mut at = 4;
+        while at < bytes.len() {
+            let (len, rest) = varlen(&bytes[at..]);
+            let content = &rest[..len as usize];
+            chunks.push((u16::from_le_bytes([content[0], content[1]]), &content[2..]));
+            at = bytes.len() - rest.len() + len as usize;
+        }
+        chunks
+    }
+
+    /// Reading a variable length integer, and what follows
+    fn varlen(bytes: &[u8]) -> (u64, &[u8]) {
+        let n = bytes[0] as usize;
+        let mut value = [0u8; 8];
+        value[..n].copy_from_slice(&bytes[1..1 + n]);
+        (u64::from_le_bytes(value), &bytes[1 + n..])
+    }
+
+    #[test]
+    fn varlen_takes_one_four_or_eight_bytes() {
+        for (value, len) in [(0, 2), (255, 2), (256, 5), (u32::MAX as u64, 5), (1 << 40, 9)] {
+            let mut out = vec![];
+            push_varlen(&mut out, value);
+            assert_eq!(out.len(), len, "{}", value);
+            assert_eq!(varlen(&out), (value, &[][..]));
+        }
+    }
+
+    #[test]
+    fn chunk_counts_tag_and_content() {
+        let mut out = vec![];
+        chunk(&mut out, SAMPLES, b"abc").unwrap();
+        assert_eq!(out, [1, 5, 3, 0, b'a', b'b', b'c']);
+        let mut out = vec![];
+        chunk(&mut out, STREAM_HEADER, &[0; 300]).unwrap();
+        assert_eq!(&out[..7], &[4, 46, 1, 0, 0, 2, 0]);
+        assert_eq!(out.len(), 7 + 300);
+    }
+
+    #[test]
+    fn clock_offsets_every_few_seconds_and_at_restarts() {
+        let mut offsets = vec![];
+        let dev_times = [0.0, 1.0, 4.9, 5.0, 7.0, 11.0, 2.0, 3.0];
+        for dev_time in dev_times {
+            let sample = Sample {
+                dev_time,
+                time: dev_time + 100.0,
+                values: vec![],
+            };
+            add_offset(&mut offsets, &sample);
+        }
+        // every OFFSET_SECS, and when the device clock goes back
+        let collected: Vec<f64> = offsets.iter().map(|(collected, _)| *collected).collect();
+        assert_eq!(collected, [0.0, 5.0, 11.0, 2.0]);
+        assert!(offsets.iter().all(|(_, offset)| *offset == 100.0));
+    }
+
+    #[test]
+    fn streams_are_chunked_and_footed() {
+        let dir = std::env::temp_dir().join(format!("ystudio-xdf-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("recording.yld");
+        let mut file = File::create(&recording).unwrap();
+        // 2500 samples of ADC, 2 MOI events in between
+        for index in 0..2500u64 {
+            let dev_time = Duration::from_millis(2 * index);
+            let time = dev_time + Duration::from_secs(10);
+            let mut sample = Ytf8 {
+                sensory: 1,
+                time: dev_time,
+                ..Default::default()
+            };
+            sample.read[0] = 4095.0;
+            let mut rows = sample.to_yld(time, time);
+            if index % 1000 == 500 {
+                sample.sensory = 0;
+                rows.extend(sample.to_yld(time, time));
+            }
+            for row in rows {
+                writeln!(file, "{}", row.to_csv_line()).unwrap();
+            }
+        }
+        drop(file);
+        let out = dir.join("recording.xdf");
+        assert_eq!(export(&recording, &out, Some(YLabVersion::Go)).unwrap(), (2, false));
+
+        let bytes = std::fs::read(&out).unwrap();
+        let chunks = chunks(&bytes);
+        let tags: Vec<u16> = chunks.iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags.iter().filter(|tag| **tag == STREAM_HEADER).count(), 2);
+        assert_eq!(tags.iter().filter(|tag| **tag == STREAM_FOOTER).count(), 2);
+        // ADC: 1000, 1000 and 500 samples, MOI in one chunk
+        let mut counts = BTreeMap::new();
+        for (_, content) in chunks.iter().filter(|(tag, _)| *tag == SAMPLES) {
+            let id = u32::from_le_bytes(content[..4].try_into().unwrap());
+            counts.entry(id).or_insert(vec![]).push(varlen(&content[4..]).0);
+        }
+        assert_eq!(counts[&1], [1000, 1000, 500]);
+        assert_eq!(counts[&2], [2]);
+        // a sample: time stamp, then three calibrated channels
+        let (_, first) = chunks.iter().find(|(tag, _)| *tag == SAMPLES).unwrap();
+        let (_, sample) = varlen(&first[4..]);
+        assert_eq!(sample[0], 8);
+        assert_eq!(f64::from_le_bytes(sample[1..9].try_into().unwrap()), 0.0);
+        assert_eq!(f64::from_le_bytes(sample[9..17].try_into().unwrap()), 3300.0);
+        // one offset in the 5 s of ADC samples, host time is 10 s ahead
+        let offsets: Vec<(f64, f64)> = chunks
+            .iter()
+            .filter(|(tag, content)| *tag == CLOCK_OFFSET && content[0] == 1)
+            .map(|(_, content)| {
+                let collected = f64::from_le_bytes(content[4..12].try_into().unwrap());
+                let offset = f64::from_le_bytes(content[12..20].try_into().unwrap());
+                (collected, offset)
+            })
+            .collect();
+        assert_eq!(offsets, [(0.0, 10.0)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,6 +7,7 @@ pub use std::fmt;
 pub use std::path::PathBuf;
 pub use std::sync::{Arc, Mutex};
 pub use std::time::Instant;
+use crate::builds::{Build, BUILDS};
 
 pub const _YLAB_EPOCH: usize = 1704063600;
 
@@ -18,6 +19,7 @@ pub struct YLab {
     sensories: Vec<Sensory>,
 }
 
+/// Sensory with number of channels and, for sampled sensories, the rate in Hz
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub enum Sensory {
     Moi(u8),
@@ -38,6 +40,30 @@ impl Sensory {
         return out;
     }
 
+    /// Kind of sensory, without channels and rate
+    pub fn kind(self) -> &'static str {
+        match self {
+            Sensory::Moi(_) => "MOI",
+            Sensory::Adc(_, _) => "ADC",
+            Sensory::Yxz(_, _) => "Yxz",
+            Sensory::Air(_) => "Air",
+        }
+    }
+
+    /// Sample rate of sampled sensories (ADC and Yxz)
+    pub fn hz(self) -> Option<usize> {
+        match self {
+            Sensory::Adc(_, hz) | Sensory::Yxz(_, hz) => Some(hz),
+            _ => None,
+        }
+    }
+
+    pub fn n_chan(self) -> u8 {
+        match self {
+            Sensory::Moi(n) | Sensory::Adc(n, _) | Sensory::Yxz(n, _) | Sensory::Air(n) => n,
+        }
+    }
+
     pub fn fft_size(self) -> Option<usize> {
         match self {
             Sensory::Adc(_, hz) | Sensory::Yxz(_, hz) => match hz {
@@ -68,6 +94,44 @@ impl Sensory {
             _ => None,
         }
     }
+
+    /// Calibration of sampled sensories (ADC and Yxz)
+    pub fn calibration(self) -> Option<Calibration> {
+        match self {
+            // 12 bit ADC with 3.3 V reference
+            Sensory::Adc(_, _) => Some(Calibration {
+                unit: "mV",
+                digital: (0.0, 4095.0),
+                physical: (0.0, 3300.0),
+            }),
+            // 16 bit signed readings of the motion sensor, not scaled
+            Sensory::Yxz(_, _) => Some(Calibration {
+                unit: "",
+                digital: (-32768.0, 32767.0),
+                physical: (-32768.0, 32767.0),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Relation between raw readings and physical values
+///
+/// Readings are linear between the ends of both ranges.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Calibration {
+    pub unit: &'static str,
+    pub digital: (f64, f64), // range of raw readings
+    pub physical: (f64, f64),
+}
+
+impl Calibration {
+    /// Physical value of a raw reading
+    pub fn to_physical(&self, raw: f64) -> f64 {
+        let (d0, d1) = self.digital;
+        let (p0, p1) = self.physical;
+        p0 + (raw - d0) * (p1 - p0) / (d1 - d0)
+    }
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -80,6 +144,16 @@ pub enum YLabVersion {
     Mini,
 }
 
+/// Labels of the motion sensors of Go Motion
+const YXZ_LABELS: [&str; MAX_YXZ as usize] = [
+    "Yxz_0", "Yxz_1", "Yxz_2", "Yxz_3", "Yxz_4", "Yxz_5", "Yxz_6", "Yxz_7",
+    "Yxz_8", "Yxz_9", "Yxz_10", "Yxz_11", "Yxz_12", "Yxz_13", "Yxz_14", "Yxz_15",
+];
+/// Most motion sensors of a Go Motion
+pub const MAX_YXZ: u8 = 16;
+/// Most banks of any version (Go Motion with MOI, ADC and MAX_YXZ Yxz)
+pub const MAX_BANKS: u8 = MAX_YXZ + 2;
+
 impl YLabVersion {
     pub fn baud(&self) -> u32 {
         match *self {
@@ -125,15 +199,68 @@ impl YLabVersion {
                 "MOI", "ADC", "Yxz_0", "Yxz_1", "Yxz_2", "Yxz_3", "Yxz_4", "Yxz_5", "Yxz_6",
                 "Yxz_7",
             ],
-            YLabVersion::GoMotion(_) => todo!(),
+            // any other number of motion sensors, by the layout of its build
+            YLabVersion::GoMotion(n) => match self.build() {
+                Some(build) => {
+                    let mut yxz = YXZ_LABELS.iter();
+                    build.layout().iter()
+                        .map(|sensory| match sensory {
+                            Sensory::Yxz(_, _) => yxz.next().copied().unwrap_or("Yxz"),
+                            sensory => sensory.kind(),
+                        })
+                        .collect()
+                }
+                None => {
+                    let mut out = vec!["MOI", "ADC"];
+                    out.extend(YXZ_LABELS.iter().take(n as usize));
+                    out
+                }
+            },
             YLabVersion::GoStress => vec!["MOI", "ADC", "Air"],
             YLabVersion::Mini => vec!["ADC"],
         }
     }
 
+    /// Sensories per bank, in the order of bank_labels
+    ///
+    /// Rates are taken from the firmware builds.
+    pub fn sensories(&self) -> Vec<Sensory> {
+        match *self {
+            YLabVersion::Pro => vec![Sensory::Moi(4), Sensory::Adc(8, 500)],
+            YLabVersion::Zet => vec![
+                Sensory::Moi(4),
+                Sensory::Adc(8, 500),
+                Sensory::Adc(8, 500),
+                Sensory::Yxz(6, 100),
+                Sensory::Yxz(6, 100),
+            ],
+            YLabVersion::Go => vec![Sensory::Moi(4), Sensory::Adc(3, 500)],
+            YLabVersion::GoMotion(_) => match self.build() {
+                Some(build) => build.layout(),
+                None => {
+                    let mut out = vec![Sensory::Moi(4), Sensory::Adc(3, 100)];
+                    out.extend((2..self.n_banks()).map(|_| Sensory::Yxz(6, 200)));
+                    out
+                }
+            },
+            YLabVersion::GoStress => vec![Sensory::Moi(4), Sensory::Adc(3, 500), Sensory::Air(3)],
+            YLabVersion::Mini => vec![Sensory::Adc(4, 100)],
+        }
+    }
+
     pub fn n_banks(&self) -> u8 {
         self.bank_labels().len().clone() as u8
     }
+
+    /// The firmware build of a Go Motion, if there is one
+    ///
+    /// Go Motion builds differ in rates and banks, e.g. Go Mo 8 has no MOI and ADC.
+    fn build(&self) -> Option<&'static Build> {
+        match *self {
+            YLabVersion::GoMotion(_) => BUILDS.iter().find(|build| build.version() == *self),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for YLabVersion {
@@ -149,19 +276,56 @@ impl fmt::Display for YLabVersion {
     }
 }
 
+/// Parsing a version from its name
+///
+/// Case, spaces, dashes and underscores are ignored,
+/// so "Go Stress", "go-stress" and "go_stress" all work.
+/// Go Motion takes the number of Yxz sensors as suffix, e.g. "go-motion-4".
+impl std::str::FromStr for YLabVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "zet" => Ok(YLabVersion::Zet),
+            "pro" => Ok(YLabVersion::Pro),
+            "go" => Ok(YLabVersion::Go),
+            "gostress" => Ok(YLabVersion::GoStress),
+            "mini" => Ok(YLabVersion::Mini),
+            "gomotion" => Ok(YLabVersion::GoMotion(1)),
+            _ => match name.strip_prefix("gomotion").map(|n| n.parse::<u8>()) {
+                Some(Ok(n)) if (1..=MAX_YXZ).contains(&n) => Ok(YLabVersion::GoMotion(n)),
+                _ => Err(format!("unknown YLab version: {}", s)),
+            },
+        }
+    }
+}
+
 /// YLab States and Commands
 ///
 /// provides the states and control commands of YLab devices
 /// YLab states are organized hierarchically to make it
 /// easier to pass on objects.
 use serialport::{self, UsbPortInfo};
-use std::io::{BufRead, BufReader};
-pub type LockedSerial = Arc<Mutex<Option<Box<dyn serialport::SerialPort + 'static>>>>;
-pub type LockedBufReader = Arc<Mutex<Option<BufReader<Box<dyn serialport::SerialPort + 'static>>>>>;
+pub type LockedSource = Arc<Mutex<Option<Box<dyn DataSource>>>>;
+use crate::clock::{ystudio_time, ClockSync};
+use crate::detect::{bauds, Detector, DETECT_VERSION};
+use crate::marks::Marks;
+use crate::yldest::YldestCmd;
+use crate::quality::Quality;
+use crate::stream::Broadcast;
+use crate::replay::Replay;
+use std::collections::HashMap;
+use crate::sources::{DataSource, Reading, SerialSource, TcpSource, TCP_PREFIX};
+use crate::ysim::{Pattern, YSim, SIMULATOR_PORT};
 
 /// YLab state
-/// + Optional list of serial port names
-pub type AvailablePorts = Option<Vec<String>>;
+/// + Optional list of serial ports
+pub type AvailablePorts = Option<Vec<YLabPort>>;
 /// + set of states for the YLab reader
 #[derive(PartialEq, Debug, Clone)]
 pub enum YLabState {
@@ -176,17 +340,223 @@ pub enum YLabState {
         version: YLabVersion,
         port_name: String,
     },
+    /// watching the traffic of a serial port for the YLab version
+    Detecting {
+        port_name: String,
+    },
+    /// the USB device has gone, waiting for it to come back
+    Reconnecting {
+        version: YLabVersion,
+        port_name: String,
+        reason: String,
+    },
+    /// connecting or reading went wrong, Connect retries, Disconnect dismisses
+    Failed {
+        version: YLabVersion,
+        port_name: String,
+        reason: String,
+    },
+}
+
+/// What can go wrong in the YLab thread
+#[derive(Debug)]
+pub enum YLabError {
+    /// the port could not be opened
+    Open { port: String, reason: String },
+    /// the source failed while reading, e.g. the USB cable came off
+    Lost { port: String, reason: String },
+    /// the recording thread does not take samples anymore
+    Storage,
+    /// the traffic fits no known build
+    Unknown { port: String, seen: String },
+}
+
+impl std::fmt::Display for YLabError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            YLabError::Open { port, reason } => write!(f, "could not open {}: {}", port, reason),
+            YLabError::Lost { port, reason } => write!(f, "lost {}: {}", port, reason),
+            YLabError::Storage => write!(f, "the recording thread has ended"),
+            YLabError::Unknown { port, seen } => {
+                write!(f, "could not tell the YLab version on {} ({}), please select it", port, seen)
+            }
+        }
+    }
+}
+
+impl std::error::Error for YLabError {}
+
+/// Time between looking for a lost YLab
+const RECONNECT_POLL: Duration = Duration::from_millis(500);
+/// Time after which a lost YLab is given up
+pub const RECONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Labels of the markers around a reconnection
+pub const MARK_LOST: &str = "ylab lost";
+pub const MARK_RECONNECTED: &str = "ylab reconnected";
+
+/// Kind of source the YLab thread reads from
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum SourceKind {
+    /// a serial port, looked for again after a dropout
+    Serial,
+    /// TCP, replay, simulator or an attached source, failing on a dropout
+    Other,
+}
+
+/// USB vendor and product IDs of the boards YLabs are built on
+pub const YLAB_USB_IDS: [(u16, u16); 4] = [
+    (0x303a, 0x1001), // Espressif ESP32-S3, native USB
+    (0x1a86, 0x55d4), // WCH CH9102 (M5Stack)
+    (0x10c4, 0xea60), // Silicon Labs CP210x
+    (0x2e8a, 0x000a), // Raspberry Pi RP2040
+];
+/// YLab firmware that names itself in the USB product string
+const YLAB_PRODUCT: &str = "YLab";
+
+/// A serial port, with what USB tells about the device
+#[derive(PartialEq, Debug, Clone, Default, serde::Serialize)]
+pub struct YLabPort {
+    pub name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+impl From<serialport::SerialPortInfo> for YLabPort {
+    fn from(info: serialport::SerialPortInfo) -> Self {
+        match info.port_type {
+            serialport::SerialPortType::UsbPort(usb) => YLabPort {
+                name: info.port_name,
+                vid: Some(usb.vid),
+                pid: Some(usb.pid),
+                manufacturer: usb.manufacturer,
+                product: usb.product,
+                serial_number: usb.serial_number,
+            },
+            _ => YLabPort { name: info.port_name, ..Default::default() },
+        }
+    }
+}
+
+impl YLabPort {
+    /// A port by name only, e.g. the simulator
+    pub fn named(name: &str) -> Self {
+        YLabPort { name: name.to_string(), ..Default::default() }
+    }
+
+    /// Is the device a board YLabs are built on, or a YLab by its product string?
+    pub fn is_ylab(&self) -> bool {
+        let by_id = matches!((self.vid, self.pid), (Some(vid), Some(pid)) if YLAB_USB_IDS.contains(&(vid, pid)));
+        let by_product = self.product.as_ref().is_some_and(|product| product.contains(YLAB_PRODUCT));
+        by_id || by_product
+    }
+}
+
+/// Port name, followed by manufacturer and serial number, as far as known
+impl fmt::Display for YLabPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let details: Vec<&str> = [&self.manufacturer, &self.product, &self.serial_number]
+            .into_iter()
+            .filter_map(|detail| detail.as_deref())
+            .collect();
+        match details.is_empty() {
+            true => write!(f, "{}", self.name),
+            false => write!(f, "{} ({})", self.name, details.join(", ")),
+        }
+    }
+}
+
+/// Serial ports with a YLab
+///
+/// Ports are found by USB vendor and product ID or product string,
+/// so YLabs show up under any system (`COM3`, `/dev/cu.usbmodem1`, `/dev/ttyUSB0`),
+/// and other serial devices (keyboards, modems) are left out.
+/// When there is no known YLab, all USB serial ports are offered,
+/// for boards Ystudio does not know yet.
+pub fn ylab_ports() -> Option<Vec<YLabPort>> {
+    let usb_ports: Vec<YLabPort> = serialport::available_ports().ok()?
+        .into_iter()
+        .map(YLabPort::from)
+        .filter(|port| port.vid.is_some())
+        .collect();
+    let ylabs: Vec<YLabPort> = usb_ports.iter().filter(|port| port.is_ylab()).cloned().collect();
+    match ylabs.is_empty() {
+        true => Some(usb_ports),
+        false => Some(ylabs),
+    }
+}
+
+/// Port name of a YLab given by its serial number, or the port name as is
+///
+/// This picks the right YLab of several, whatever port the system gave it.
+pub fn resolve_port(port_name: &str) -> String {
+    ylab_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|port| port.serial_number.as_deref() == Some(port_name))
+        .map_or(port_name.to_string(), |port| port.name)
+}
+
+/// USB device behind a serial port, if it is one
+pub fn usb_info(port_name: &str) -> Option<UsbPortInfo> {
+    serialport::available_ports().ok()?
+        .into_iter()
+        .find(|p| p.port_name == port_name)
+        .and_then(|p| match p.port_type {
+            serialport::SerialPortType::UsbPort(info) => Some(info),
+            _ => None,
+        })
+}
+
+/// Port of a USB device, which may come back under another name
+///
+/// Devices are the same by vendor, product and serial number.
+/// Without USB information, the port must have the same name.
+fn find_port(port_name: &str, usb: Option<&UsbPortInfo>) -> Option<String> {
+    serialport::available_ports().ok()?
+        .into_iter()
+        .find(|p| match (usb, &p.port_type) {
+            (Some(usb), serialport::SerialPortType::UsbPort(info)) =>
+                info.vid == usb.vid && info.pid == usb.pid && info.serial_number == usb.serial_number,
+            (Some(_), _) => false,
+            (None, _) => p.port_name == port_name,
+        })
+        .map(|p| p.port_name)
 }
+
 /// + set of commands to control the YLab
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug)]
 pub enum YLabCmd {
     Disconnect,
     Connect {
         version: YLabVersion,
         port_name: String,
     },
+    /// connecting to a serial port and detecting the version
+    Detect {
+        port_name: String,
+    },
     Read {},
     Stop {},
+    Replay {
+        version: YLabVersion,
+        path: PathBuf,
+        speed: f64,
+        looping: bool,
+    },
+    Simulate {
+        version: YLabVersion,
+        patterns: Vec<Pattern>, // one per bank
+    },
+    /// any other source of samples
+    Attach {
+        version: YLabVersion,
+        source: Box<dyn DataSource>,
+    },
+    /// answered once the commands sent before have taken effect
+    Sync(mpsc::Sender<()>),
 }
 
 use egui::emath::History;
@@ -196,26 +566,90 @@ use std::thread;
 #[allow(unused_imports)]
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Task for reading data from serial port
+/// Task for reading data from a data source
+/// (serial port, replayed recording, simulator, TCP or attached)
 ///
 /// ylab_state is used for state transitions
 /// yld_wind is used for storing data
 /// ylab_listen is used for listening to commands
 ///
 
+#[allow(clippy::too_many_arguments)]
 pub fn ylab_thread(
     ylab_state: Arc<Mutex<YLabState>>,        // shared state
     ylab_listen: mpsc::Receiver<YLabCmd>,     // receiving comands
     yld_wind: Arc<Mutex<History<data::Yld>>>, // Yld history shared with UI (FFT) and storage
     ytf_wind: Arc<Mutex<data::Banks>>,        // Ytf8 history to share with UI (plot)
-    yld_st: mpsc::Sender<data::Yld>,          // sending data to storage
+    yld_st: mpsc::Sender<Vec<data::Yld>>,     // sending data to storage, whole samples
+    quality: Arc<Mutex<Quality>>,             // data quality counters shared with UI and storage
+    live: Broadcast,                          // publishing samples to other programs
+    marks: Marks,                             // marking reconnections in recordings
+    yldest_cmd: mpsc::Sender<YldestCmd>,      // syncing recordings when the YLab is lost
 ) -> ! {
-    // Preparing serial port and buffer
-    let serialport: LockedSerial = Arc::new(Mutex::new(None));
-    let bufreader: LockedBufReader = Arc::new(Mutex::new(None));
+    // The connected source
+    let source: LockedSource = Arc::new(Mutex::new(None));
+    // Device clocks, mapped onto the Ystudio clock
+    let clocks: Mutex<HashMap<u8, ClockSync>> = Mutex::new(HashMap::new());
+    // Kind of the source being read
+    let source_kind = Mutex::new(SourceKind::Other);
+    // USB device of the serial port being read, for finding it again
+    let usb: Mutex<Option<UsbPortInfo>> = Mutex::new(None);
+    // Connecting a source: transition to Connected, named after the source
+    let attach = |version: YLabVersion, this_source: Box<dyn DataSource>, kind: SourceKind| {
+        let port_name = this_source.name();
+        // only a serial port is looked for again after a dropout
+        *source_kind.lock().unwrap() = kind;
+        *usb.lock().unwrap() = match kind {
+            SourceKind::Serial => usb_info(&port_name),
+            SourceKind::Other => None,
+        };
+        // a new source has its own clock and counters
+        clocks.lock().unwrap().clear();
+        *quality.lock().unwrap() = Quality::new();
+        *source.lock().unwrap() = Some(this_source);
+        *ylab_state.lock().unwrap() = YLabState::Connected { version, port_name: port_name.clone() };
+        println!("Connected to {} {}", version, port_name);
+    };
+    // Start of the current reconnection
+    let mut lost_at = Instant::now();
+    // Traffic seen while detecting the version
+    let mut detector = Detector::new();
+    // Baud rates still to try while detecting
+    let mut detect_bauds: Vec<u32> = vec![];
+    // Going to Failed, closing the source
+    let fail = |version: YLabVersion, port_name: String, error: YLabError| {
+        eprintln!("{}", error);
+        *source.lock().unwrap() = None;
+        *ylab_state.lock().unwrap() = YLabState::Failed { version, port_name, reason: error.to_string() };
+    };
 
-    // Unique time stamp and offset
-    let start_time = Instant::now();
+
+    // Forwarding a sample to the plot windows and to storage
+    // The windows use arrival time, recordings the device time
+    let forward = |sample: data::Ytf8, version: YLabVersion| -> Result<(), YLabError> {
+        let ystudio_time = ystudio_time();
+        let bank = sample.sensory;
+        quality.lock().unwrap().check(&sample, version.sensories().get(bank as usize).copied());
+        if (bank as usize) < version.bank_labels().len() {
+            if let Some(window) = ytf_wind.lock().unwrap().get_mut(bank as usize) {
+                window.add(ystudio_time.as_secs_f64(), sample);
+            }
+        }
+        let time = {
+            let mut clocks = clocks.lock().unwrap();
+            let clock = clocks.entry(sample.dev).or_default();
+            clock.update(sample.time, ystudio_time);
+            clock.map(sample.time)
+        };
+        live.publish_sample(&sample, time, ystudio_time, version);
+        let yld = sample.to_yld(time, ystudio_time);
+        for measure in yld.iter() {
+            yld_wind.lock().unwrap().add(ystudio_time.as_secs_f64(), *measure);
+        }
+        // in one piece, so that recordings start and stop between samples
+        yld_st.send(yld).map_err(|_| YLabError::Storage)?;
+        Ok(())
+    };
 
     loop {
         // capture YLab state and incoming commands from the UI
@@ -226,31 +660,134 @@ pub fn ylab_thread(
         // state changes on command
         // beautiful!
         match (this_ylab_state, this_cmd){
+            // the command before has been dealt with in the last round
+            (_, Some(YLabCmd::Sync(ack)))
+            => {let _ = ack.send(());},
+
             // Waiting for available ports and command
             (YLabState::Disconnected { ports: _ },
              None)
             => {
-                let avail_ports = serialport::available_ports().ok();
-                // incorrect! problem: There is often a keyboard on serial, so it is never empty.
+                let avail_ports = ylab_ports();
                 match avail_ports {
                     None => {
                         thread::sleep(Duration::from_millis(100));
                         // no ports: try again in 100ms, no transition
                     },
-                    Some(found) => {
+                    Some(mut found) => {
                         // ports found: transition to Disconnected with available ports
-                        let port_names= found.iter()
-                                .map(|p| p.clone().port_name)
-                                .filter(|p| p.starts_with("/dev/ttyACM"))
-                                .collect::<Vec<String>>();
+                        // the simulator is always available
+                        found.push(YLabPort::named(SIMULATOR_PORT));
                         // automatically proceed to Disconnected with available ports
                         *ylab_state.lock().unwrap() = YLabState::Disconnected{
-                                                        ports: Some(port_names)};
+                                                        ports: Some(found)};
+                    },
+                }
+                },
+
+            // Connecting the simulator, also for retrying after failure
+            (YLabState::Disconnected { ports: Some(_) } | YLabState::Failed { .. },
+             Some(YLabCmd::Connect { version, port_name }))
+                if port_name == SIMULATOR_PORT
+            => attach(version, Box::new(YSim::new(version)), SourceKind::Other),
+
+            (YLabState::Disconnected { ports: _ } | YLabState::Failed { .. },
+             Some(YLabCmd::Simulate { version, patterns }))
+            => {
+                let mut this_sim = YSim::new(version);
+                for (bank, pattern) in patterns.into_iter().enumerate() {
+                    this_sim = this_sim.with_pattern(bank as u8, pattern);
+                }
+                attach(version, Box::new(this_sim), SourceKind::Other);
+                },
+
+            // Connecting a networked YLab
+            (YLabState::Disconnected { ports: _ } | YLabState::Failed { .. },
+             Some(YLabCmd::Connect { version, port_name }))
+                if port_name.starts_with(TCP_PREFIX)
+            => {
+                match TcpSource::connect(&port_name) {
+                    Err(e) => {
+                        let error = YLabError::Open { port: port_name.clone(), reason: e.to_string() };
+                        fail(version, port_name, error)},
+                    Ok(tcp) => attach(version, Box::new(tcp), SourceKind::Other),
+                    };
+                },
+
+            // Connecting a serial port without knowing the version,
+            // the samples of the first seconds tell
+            (YLabState::Disconnected { ports: Some(_) } | YLabState::Failed { .. },
+             Some(YLabCmd::Detect { port_name }))
+            => {
+                let port_name = resolve_port(&port_name);
+                detect_bauds = bauds();
+                match SerialSource::open_at(&port_name, detect_bauds.remove(0)) {
+                    Err(e) => {
+                        let error = YLabError::Open { port: port_name.clone(), reason: e.to_string() };
+                        fail(DETECT_VERSION, port_name, error)},
+                    Ok(serial) => {
+                        *source.lock().unwrap() = Some(Box::new(serial));
+                        detector = Detector::new();
+                        println!("Detecting the YLab on {}", port_name);
+                        *ylab_state.lock().unwrap() = YLabState::Detecting { port_name };
                     },
+                };
+                },
+
+            (YLabState::Detecting { port_name }, None)
+            => {
+                let reading = match source.lock().unwrap().as_mut() {
+                    Some(this_source) => this_source.next_sample(),
+                    None => Reading::Closed,
+                };
+                match reading {
+                    Reading::Sample(sample) => detector.add(&sample),
+                    Reading::Empty => thread::sleep(Duration::from_millis(1)),
+                    Reading::Invalid(_) => {},
+                    Reading::Closed => thread::sleep(Duration::from_millis(100)),
+                    Reading::Lost(reason) => {
+                        let error = YLabError::Lost { port: port_name.clone(), reason };
+                        fail(DETECT_VERSION, port_name, error);
+                        continue;
+                    },
+                }
+                if detector.is_done() {
+                    match detector.detect() {
+                        // a UART bridge only sends readable data at the right baud rate
+                        None if !detect_bauds.is_empty() => {
+                            let baud = detect_bauds.remove(0);
+                            println!("Nothing fits ({}), trying {} baud", detector.summary(), baud);
+                            *source.lock().unwrap() = None;
+                            match SerialSource::open_at(&port_name, baud) {
+                                Err(e) => {
+                                    let error = YLabError::Open { port: port_name.clone(), reason: e.to_string() };
+                                    fail(DETECT_VERSION, port_name, error)},
+                                Ok(serial) => {
+                                    *source.lock().unwrap() = Some(Box::new(serial));
+                                    detector = Detector::new();
+                                },
+                            }
+                        },
+                        None => {
+                            let error = YLabError::Unknown { port: port_name.clone(), seen: detector.summary() };
+                            fail(DETECT_VERSION, port_name, error)},
+                        Some(build) => {
+                            let version = build.version();
+                            println!("Detected {} ({}), reading as {}", build.name(), detector.summary(), version);
+                            // the baud rate that gave readable data is kept
+                            let this_source = source.lock().unwrap().take();
+                            match this_source {
+                                Some(this_source) => attach(version, this_source, SourceKind::Serial),
+                                None => {
+                                    let error = YLabError::Open { port: port_name.clone(), reason: "port closed".to_string() };
+                                    fail(version, port_name, error)},
+                            }
+                        },
+                    }
                 }
                 },
 
-            (YLabState::Disconnected { ports: Some(_) },
+            (YLabState::Disconnected { ports: Some(_) } | YLabState::Failed { .. },
              Some(YLabCmd::Connect { version, port_name }))
             => {
                 // We make one connection attempt to verify the port
@@ -258,98 +795,118 @@ pub fn ylab_thread(
                 // YLab, e.g. which sensors of a bank to collect.
                 // If Rust holds its promise
                 // the serial port is properly closed when going out of scope
-                let poss_port =
-                    serialport::new(port_name.clone(),
-                        version.baud() as u32)
-                        .timeout(Duration::from_millis(1))
-                        .flow_control(serialport::FlowControl::Software)
-                        .open(); // ok() turns a Result into an Option
-                match poss_port {
-                    Err(_) => {eprintln!("connection failed"); thread::sleep(Duration::from_millis(500))},
-                    Ok(real_port)
-                        => {*serialport.lock().unwrap() = Some(real_port);
-
-                            // transition to Connected
-                            *ylab_state.lock().unwrap() = YLabState::Connected {
-                                                            version: version,
-                                                            port_name: port_name.clone()};
-                            println!("Connected to {}", port_name.clone());},
+                // a YLab can also be given by its serial number
+                let port_name = resolve_port(&port_name);
+                match SerialSource::open(&port_name, version) {
+                    Err(e) => {
+                        let error = YLabError::Open { port: port_name.clone(), reason: e.to_string() };
+                        fail(version, port_name, error)},
+                    Ok(serial) => attach(version, Box::new(serial), SourceKind::Serial),
+                    };
+                },
 
+            // Replaying a recording as if it came from a YLab
+            (YLabState::Disconnected { ports: _ } | YLabState::Failed { .. },
+             Some(YLabCmd::Replay { version, path, speed, looping }))
+            => {
+                match Replay::open(&path, speed, looping) {
+                    Err(e) => {
+                        let port_name = path.display().to_string();
+                        let error = YLabError::Open { port: port_name.clone(), reason: e.to_string() };
+                        fail(version, port_name, error)},
+                    Ok(this_replay) => {
+                        println!("Replaying {:?} at {}x", path, speed);
+                        attach(version, Box::new(this_replay), SourceKind::Other)},
                     };
                 },
 
+            // Any other source
+            (YLabState::Disconnected { ports: _ } | YLabState::Failed { .. },
+             Some(YLabCmd::Attach { version, source: this_source }))
+            => attach(version, this_source, SourceKind::Other),
+
             // Start reading on command
             (YLabState::Connected {version, ref port_name}, _)  // jumping over connect state
             //Some(YLabCmd::Read {}))
-            => {*bufreader.lock().unwrap()
-                    = Some(BufReader::new(serialport.lock().unwrap().take().unwrap()));
-                *ylab_state.lock().unwrap() = YLabState::Reading {version: version.clone(),
+            => {*ylab_state.lock().unwrap() = YLabState::Reading {version: version.clone(),
                     port_name: port_name.clone()};
                 },
 
-            (YLabState::Reading {version, port_name:_, },
+            (YLabState::Reading {version, port_name, },
             None)
-                // We are already in a fast loop, so we read one line at a time.
-                =>  {let mut reader = bufreader.lock().unwrap();
-                    match reader.as_mut().unwrap().lines().next(){
-                        // buffer empty
-                        None => {eprint!("No line"); continue},
-                        // line found
-                        Some(line)
-                            => match line {
-                                // conversion error
-                                Err(_) => {continue},
-                                // conversion success
-                                Ok(line) => {
-                                    // parse line into Ytf8
-                                    match data::Ytf8::from_csv_line(&line) {
-                                        // not a Ytf8 line
-                                        Err(e) => {eprintln!("Not Ytf8: {:?}", e); continue}
-                                        // Ytf8 line,
-                                        Ok(sample) => {
-                                            let ystudio_time = Instant::now().duration_since(start_time);
-                                            let bank = sample.sensory;
-                                            if (bank as usize) < version.bank_labels().len() {
-                                                ytf_wind.lock().unwrap()[bank as usize]
-                                                        .add(ystudio_time.as_secs_f64(), sample.clone());
-                                            //ytf_out.send(sample).unwrap();
-}
-                                            let yld = sample.to_yld(ystudio_time);
-                                            for measure in yld.iter() {
-                                                yld_wind.lock().unwrap()
-                                                        .add(ystudio_time.as_secs_f64(), measure.clone());
-                                                yld_st.send(measure.clone()).unwrap();
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
+                // We are already in a fast loop, so we read one sample at a time.
+                =>  {let reading = match source.lock().unwrap().as_mut() {
+                        Some(this_source) => this_source.next_sample(),
+                        None => Reading::Closed,
+                    };
+                    match reading {
+                        Reading::Sample(sample) => {
+                            if let Err(error) = forward(sample, version) {
+                                fail(version, port_name, error);
                             }
+                        },
+                        // nothing due yet
+                        Reading::Empty => thread::sleep(Duration::from_millis(1)),
+                        Reading::Invalid(e) => {
+                            quality.lock().unwrap().parse_error();
+                            eprintln!("{}", e)},
+                        // end of source, waiting for disconnect
+                        Reading::Closed => thread::sleep(Duration::from_millis(100)),
+                        // a serial port may come back, e.g. after the cable was wiggled
+                        Reading::Lost(reason) if *source_kind.lock().unwrap() == SourceKind::Serial => {
+                            eprintln!("Lost {}: {}, reconnecting", port_name, reason);
+                            // what was recorded so far goes to disk, in case it does not come back
+                            let _ = yldest_cmd.send(YldestCmd::Flush);
+                            *source.lock().unwrap() = None;
+                            marks.mark(MARK_LOST);
+                            lost_at = Instant::now();
+                            *ylab_state.lock().unwrap() = YLabState::Reconnecting { version, port_name, reason };
+                        },
+                        Reading::Lost(reason) => {
+                            let _ = yldest_cmd.send(YldestCmd::Flush);
+                            let error = YLabError::Lost { port: port_name.clone(), reason };
+                            fail(version, port_name, error)},
+                    }
                     },
 
-
-
+            // Looking for the lost device, reading on when it is back
+            (YLabState::Reconnecting { version, port_name, reason },
+            None)
+            => {
+                thread::sleep(RECONNECT_POLL);
+                let found = find_port(&port_name, usb.lock().unwrap().as_ref());
+                match found.map(|found| (SerialSource::open(&found, version), found)) {
+                    Some((Ok(serial), found)) => {
+                        *source.lock().unwrap() = Some(Box::new(serial));
+                        marks.mark(MARK_RECONNECTED);
+                        println!("Reconnected {} as {}", port_name, found);
+                        *ylab_state.lock().unwrap() = YLabState::Reading { version, port_name: found };
+                    },
+                    // not back (yet), or not ready to be opened
+                    _ if lost_at.elapsed() < RECONNECT_TIMEOUT => {},
+                    _ => {
+                        let error = YLabError::Lost { port: port_name.clone(), reason };
+                        fail(version, port_name, error)},
+                }
+                },
 
             (YLabState::Reading {version, port_name},
             Some(YLabCmd::Disconnect {  }))
             => {*ylab_state.lock().unwrap() = YLabState::Connected{version, port_name};//YLabState::Disconnected{ports: None};
-                let this_serial = bufreader.lock().unwrap().take().unwrap().into_inner();
-                *serialport.lock().unwrap() = Some(this_serial); // It has been taken, so we put it back
-                *bufreader.lock().unwrap() = None;
                 println!("Stopped reading");
+                // dropping the source closes it
+                *source.lock().unwrap() = None;
                 *ylab_state.lock().unwrap() = YLabState::Disconnected { ports: None };
-                *bufreader.lock().unwrap() = None;
-                *serialport.lock().unwrap() = None;
                 println!("Disconnected");
                 },
 
-            // Disconnect on command
-            (YLabState::Connected{version:_, port_name:_},
+            // Disconnect on command, or dismissing a failure, or giving up reconnecting or detecting
+            (YLabState::Connected{version:_, port_name:_} | YLabState::Failed { .. }
+                | YLabState::Reconnecting { .. } | YLabState::Detecting { .. },
                 Some(YLabCmd::Disconnect{}))
                 => {
                     *ylab_state.lock().unwrap() = YLabState::Disconnected { ports: None };
-                    *bufreader.lock().unwrap() = None;
-                    *serialport.lock().unwrap() = None;
+                    *source.lock().unwrap() = None;
                     println!("Disconnected");
                 },
             (_,_)   => {},
@@ -366,10 +923,11 @@ pub mod data {
     /// YLab Long Data
     ///
     /// YLD keeps data one row per measure with
-    /// + a time stamp,
+    /// + a time stamp (device time, mapped onto Ystudio time),
     /// + a device identifier
     /// + a sensory index (position in the bank)
     /// + one measurement value
+    /// + the raw device time and the arrival time at Ystudio
     ///
     #[derive(Copy, Clone, Debug)]
     pub struct Yld {
@@ -378,23 +936,76 @@ pub mod data {
         pub sensory: u8,
         pub chan: u8,
         pub value: f64,
+        pub dev_time: Duration,
+        pub arrival: Duration,
     }
 
     impl Yld {
         pub fn to_csv_line(&self) -> String {
             let mut out = String::new();
             out.push_str(&self.time.as_secs_f64().to_string());
-            out.push_str(",");
+            out.push(',');
             out.push_str(&self.dev.to_string());
-            out.push_str(",");
+            out.push(',');
             out.push_str(&self.sensory.to_string());
-            out.push_str(",");
+            out.push(',');
             out.push_str(&self.chan.to_string());
-            out.push_str(",");
+            out.push(',');
             out.push_str(&self.value.to_string());
+            out.push(',');
+            out.push_str(&self.dev_time.as_secs_f64().to_string());
+            out.push(',');
+            out.push_str(&self.arrival.as_secs_f64().to_string());
             out.push_str("\r\n");
             return out;
         }
+
+        /// Reading a measure back from a line of a Yld file
+        ///
+        /// The line is expected to have 7 columns:
+        /// time in seconds, device, sensory, channel, value,
+        /// device time and arrival time in seconds.
+        /// Older recordings have only the first 5 columns,
+        /// their time is used as device and arrival time.
+        pub fn from_csv_line(line: &str) -> Result<Yld, ParseError> {
+            let cols: Vec<&str> = line.trim().split(',').collect();
+            if cols.len() != 5 && cols.len() != 7 {
+                return Err(ParseError::Len(cols.len()));
+            }
+            let secs = |col: &str| match col.parse::<f64>() {
+                // negative, infinite or too large for a Duration
+                Ok(secs) => Duration::try_from_secs_f64(secs)
+                    .map_err(|_| ParseError::Time(col.to_string())),
+                _ => Err(ParseError::Time(col.to_string())),
+            };
+            let time = secs(cols[0])?;
+            let (dev_time, arrival) = match cols.len() {
+                7 => (secs(cols[5])?, secs(cols[6])?),
+                _ => (time, time),
+            };
+            let dev = cols[1]
+                .parse::<u8>()
+                .map_err(|_| ParseError::Dev(cols[1].to_string()))?;
+            let sensory = cols[2]
+                .parse::<u8>()
+                .map_err(|_| ParseError::Sensory(cols[2].to_string()))?;
+            let chan = match cols[3].parse::<u8>() {
+                Ok(chan) if chan < 8 => chan,
+                _ => return Err(ParseError::Chan(cols[3].to_string())),
+            };
+            let value = cols[4]
+                .parse::<f64>()
+                .map_err(|_| ParseError::Value(cols[4].to_string()))?;
+            Ok(Yld {
+                time,
+                dev,
+                sensory,
+                chan,
+                value,
+                dev_time,
+                arrival,
+            })
+        }
     }
 
     pub type _YldBuf = Vec<Yld>;
@@ -447,6 +1058,18 @@ pub mod data {
         }
     }
 
+    impl SplitByChan for Vec<(f64, Ytf8)> {
+        fn split(&self) -> MultiLines<8> {
+            let mut out = new_multi_lines();
+            for (time, sample) in self.iter() {
+                for (chan, value) in sample.read.iter().enumerate() {
+                    out[chan].push([*time, *value]);
+                }
+            }
+            out
+        }
+    }
+
     /// YLab transport format (YTF)
     ///
     /// YLabs send data with a time stamp,
@@ -496,6 +1119,8 @@ pub mod data {
         Dev(String),
         Sensory(String),
         Time(String),
+        Chan(String),
+        Value(String),
     }
 
     /// Result type for parsing CSV lines
@@ -515,7 +1140,7 @@ pub mod data {
         /// It's like every run has their own epoch.
         ///
         /// Collecting a sample fro a CSV line is a fallible operation.
-        pub fn from_csv_line(line: &String) -> FailableSample {
+        pub fn from_csv_line(line: &str) -> FailableSample {
             // splitting
             let cols: Vec<&str> = line.split(",").collect();
             // check correct length
@@ -526,8 +1151,9 @@ pub mod data {
             //let millis = cols[0].parse::<i64>();
             let time: Duration;
             match cols[0].trim().parse::<i64>() {
-                Ok(millis) => time = Duration::from_millis(millis.try_into().unwrap()),
-                Err(_) => return Err(ParseError::Time(cols[0].to_string())),
+                // device time can not be negative
+                Ok(millis) if millis >= 0 => time = Duration::from_millis(millis as u64),
+                _ => return Err(ParseError::Time(cols[0].to_string())),
             }
 
             // extract dev number
@@ -543,7 +1169,7 @@ pub mod data {
             let mut read: [f64; 8] = [0., 0., 0., 0., 0., 0., 0., 0.];
             for chn in 0..8 {
                 // parse value
-                let value = cols[chn + 2].parse::<f64>();
+                let value = cols[chn + 2].trim().parse::<f64>();
                 match value {
                     Ok(v) => read[chn] = v,
                     Err(_) => read[chn] = 0.0,
@@ -557,10 +1183,12 @@ pub mod data {
             })
         }
 
-        #[allow(dead_code)]
+        /// Writing a sample as CSV line, like the YLab sends it
+        ///
+        /// time in ms, sensory and 8 readings (no line ending)
         pub fn to_csv_line(&self) -> String {
             let out: String = [
-                [self.time.as_millis().to_string(), self.dev.to_string()].join(","),
+                [self.time.as_millis().to_string(), self.sensory.to_string()].join(","),
                 self.read.map(|r| r.to_string()).join(","),
             ]
             .join(",");
@@ -568,7 +1196,11 @@ pub mod data {
             return out;
         }
 
-        pub fn to_yld(&self, time: Duration) -> Vec<Yld> {
+        /// Long format of a sample
+        ///
+        /// `time` is the device time on the Ystudio time line (see ClockSync),
+        /// `arrival` when the sample arrived at Ystudio.
+        pub fn to_yld(&self, time: Duration, arrival: Duration) -> Vec<Yld> {
             let mut out: Vec<Yld> = Vec::new();
             let mut chan: u8 = 0;
             for value in self.read.iter() {
@@ -578,6 +1210,8 @@ pub mod data {
                     sensory: self.sensory,
                     chan: chan,
                     value: *value as f64,
+                    dev_time: self.time,
+                    arrival,
                 });
                 chan += 1;
             }
@@ -1,6 +1,12 @@
 
+use crate::marks::{marks_path, Mark};
+use crate::meta::{self, Meta};
+use crate::quality::Quality;
+use crate::segments::{self, Rotation};
+use crate::recovery::{self, RecordingLock};
+use crate::sinks::{self, Compression, Format, Sink};
 use crate::ylab::data::*;
-use std::io::Write;
+use std::io::{self, Write};
 use std::sync::*;
 use std::fs;
 use std::path::PathBuf;
@@ -9,121 +15,309 @@ use std::path::PathBuf;
 pub enum YldestState {
     Idle {dir:Option<PathBuf>},
     Connected {path:PathBuf},
-    Recording {path: PathBuf}}
+    Recording {path: PathBuf},
+    // recording went wrong, New starts another one, Stop dismisses
+    Failed {dir: Option<PathBuf>, reason: String}}
+
+/// What can go wrong in the Yldest thread
+#[derive(Debug)]
+pub enum YldestError {
+    /// neither the command nor the state has a directory
+    NoDirectory,
+    /// the system clock is before 1970, so no automatic file name
+    Clock,
+    Create {path: PathBuf, source: io::Error},
+    /// e.g. a full disk or a removed drive
+    Write {path: PathBuf, source: io::Error},
+}
+
+impl std::fmt::Display for YldestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            YldestError::NoDirectory => write!(f, "no directory to record to"),
+            YldestError::Clock => write!(f, "system time is before 1970"),
+            YldestError::Create {path, source} => write!(f, "could not create {:?}: {}", path, source),
+            YldestError::Write {path, source} => write!(f, "could not write {:?}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for YldestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            YldestError::Create {source, ..} | YldestError::Write {source, ..} => Some(source),
+            _ => None,
+        }
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Clone)]
 pub enum YldestCmd {
-    New {change_dir: Option<PathBuf>, file_name: Option<PathBuf>},
-    Record, // add let _ = yld, so that collection truly goes on
+    New {change_dir: Option<PathBuf>, file_name: Option<PathBuf>, format: Format, compression: Compression, rotation: Rotation, meta: Box<Meta>},
+    Record, // resume after Pause
     Pause,
     Stop,
+    Flush, // write out and sync, e.g. when the YLab disconnects
+    Sync(mpsc::Sender<()>), // answered once the commands before have taken effect
 }
 
+/// Time between flushes while recording
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
 use std::time::SystemTime;
-pub fn auto_file_name() -> PathBuf {
+pub fn auto_file_name(format: Format, compression: Compression) -> Result<PathBuf, YldestError> {
     match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
         Ok(n) => {
-            let id = n.as_secs() - 1699743807;
-            PathBuf::from(format!("{}.yld", id))
+            let id = n.as_secs().saturating_sub(1699743807);
+            Ok(sinks::compressed_path(PathBuf::from(format!("{}.{}", id, format.extension())), format, compression))
             },
-        Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+        Err(_) => Err(YldestError::Clock),
     }
 }
 
 type LockedPath = Arc<Mutex<Option<PathBuf>>>;
 type LockedFile = Arc<Mutex<Option<File>>>;
+type LockedSink = Arc<Mutex<Option<Box<dyn Sink>>>>;
 type LockedState = Arc<Mutex<YldestState>>;
 
 use std::fs::File;
-//use std::time::Duration;
+use std::time::{Duration, Instant};
 //use std::time::UNIX_EPOCH;
 pub fn yldest_thread(
     state: LockedState,
     listen: mpsc::Receiver<YldestCmd>,
-    incoming: mpsc::Receiver<Yld>,
+    incoming: mpsc::Receiver<Vec<Yld>>, // the rows of one sample at a time
+    quality: Arc<Mutex<Quality>>, // data quality counters of the YLab thread
+    marks: mpsc::Receiver<Mark>,  // event markers, written next to the recording
 ) -> ! {
     let locked_path: LockedPath = Arc::new(Mutex::new(None));
     let locked_dir: LockedPath = Arc::new(Mutex::new(None));
-    let locked_file: LockedFile = Arc::new(Mutex::new(None));
-    let mut write_buffer = String::new();
+    let locked_sink: LockedSink = Arc::new(Mutex::new(None));
+    let locked_marks: LockedFile = Arc::new(Mutex::new(None));
+    // metadata of the current recording, quality counts at its start
+    let mut meta = Meta::default();
+    let mut quality_start = Quality::new();
+    let mut last_flush = Instant::now();
+    // held while a recording is open, so that recovery leaves it alone
+    let mut recording_lock: Option<RecordingLock> = None;
+    // errors name the file being recorded
+    let write_error = |source: io::Error| YldestError::Write {
+        path: locked_path.lock().unwrap().clone().unwrap_or_default(), source};
     loop {
-        // getting the current state, incoming commands and incoming data
+        // getting the current state and incoming commands
         let this_state = state.lock().unwrap().clone();
         let this_cmd = listen.try_recv().ok();
-        let measure = incoming.try_recv().ok();
-        // match the current state, command and data stream to do transitions
-        match (this_state, this_cmd, measure) {
+        if matches!(this_state, YldestState::Idle{..} | YldestState::Failed{..}) {
+            recording_lock = None;
+        }
+        // markers are written while recording, and dropped otherwise
+        for mark in marks.try_iter() {
+            if let (YldestState::Recording{..}, Some(file)) = (&this_state, locked_marks.lock().unwrap().as_mut()) {
+                if let Err(e) = file.write_all(mark.to_csv_line().as_bytes()) {
+                    eprintln!("Could not write marker: {}", e);
+                }
+            }
+        }
+        // what is recorded goes to the file every second, even without new data
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            last_flush = Instant::now();
+            let flushed = match locked_sink.lock().unwrap().as_mut() {
+                Some(sink) => sink.flush(),
+                None => Ok(()),
+            };
+            if let Err(e) = flushed {
+                fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, write_error(e));
+                continue;
+            }
+        }
+        // match the current state and command to do transitions
+        match (this_state, this_cmd) {
+
+            // the command before has been dealt with in the last round
+            (_, Some(YldestCmd::Sync(ack)))
+            => {let _ = ack.send(());},
 
             // start recording on command, None is default path
-            (YldestState::Idle{dir}, 
-            Some(YldestCmd::New {change_dir, file_name }),
-            _) 
+            // also after a failure, e.g. once there is space on the disk again
+            (YldestState::Idle{dir} | YldestState::Failed{dir, ..}, 
+            Some(YldestCmd::New {change_dir, file_name, format, compression, rotation, meta: new_meta }))
             => { 
-                match (change_dir, file_name, dir) {
+                meta = *new_meta;
+                meta.started = Some(meta::now());
+                quality_start = quality.lock().unwrap().clone();
+                let path = match (change_dir, file_name, dir) {
                     (None, _, None) 
-                    => {eprintln! ("No path given, no default path set, no recording started.")},
+                    => Err(YldestError::NoDirectory),
                     // both dir and name are given
                     (Some(chdir) , Some(file_name), _) 
                     => {*locked_dir.lock().unwrap() = Some(chdir.clone());
-                        let path = chdir.join(file_name);
-                        *locked_path.lock().unwrap() = Some(path.clone());
-                        *locked_file.lock().unwrap() = Some(fs::File::create(&path).unwrap());
-                        *state.lock().unwrap() = YldestState::Connected {path: path.clone()};
-                        println!("Recording to {:?}", path);},
+                        Ok(sinks::compressed_path(chdir.join(file_name), format, compression))},
                     // a file name is given, but no directory: try using existin locked_dir
                     (None , Some(path), Some(dir))
-                    => {
-                        let path = dir.join(path);
-                        *locked_path.lock().unwrap() = Some(path.clone()); 
-                        *locked_file.lock().unwrap() = Some(fs::File::create(&path).unwrap());
-                        *state.lock().unwrap() = YldestState::Connected {path: path.clone()};
-                        println!("Recording to {:?}", path);},
+                    => Ok(sinks::compressed_path(dir.join(path), format, compression)),
                     // a directory is given, but no file name -> auto naming
                     (Some(dir), None, _) | (None, None, Some(dir))
                     => {
                         *locked_dir.lock().unwrap() = Some(dir.clone()); 
-                        let path = auto_file_name();
-                        *locked_path.lock().unwrap() = Some(path.clone()); 
-                        *locked_file.lock().unwrap() = Some(fs::File::create(&path).unwrap());
-                        *state.lock().unwrap() = YldestState::Connected {path: path.clone()};
-                        println!("Recording to {:?}", path);},
-                    }},
+                        auto_file_name(format, compression).map(|name| dir.join(name))},
+                    };
+                // locked before anything is written, segmented recordings go by their manifest
+                let created = path.and_then(|path| {
+                    recording_lock = Some(RecordingLock::acquire(&path)
+                        .map_err(|source| YldestError::Create {path: path.clone(), source})?);
+                    if let Some(dir) = path.parent() {
+                        if let Err(e) = recovery::remember_dir(dir) {
+                            eprintln!("Could not remember {:?} for recovery: {}", dir, e);
+                        }
+                    }
+                    segments::create(&path, format, compression, rotation, &meta)
+                        .map_err(|source| YldestError::Create {path, source})
+                });
+                match created {
+                    Err(error) => fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, error),
+                    Ok((path, sink)) => {
+                        *locked_path.lock().unwrap() = Some(path.clone());
+                        *locked_sink.lock().unwrap() = Some(sink);
+                        println!("Recording to {:?}", path);
+                        // the sidecar is written right away, and finalised on Stop
+                        if let Err(e) = meta.write(&path) {
+                            eprintln!("Could not write metadata of {:?}: {}", path, e);
+                        }
+                        match fs::File::create(marks_path(&path)) {
+                            Ok(file) => *locked_marks.lock().unwrap() = Some(file),
+                            Err(e) => eprintln!("Could not create markers of {:?}: {}", path, e),
+                        }
+                        // a new recording starts right away
+                        *state.lock().unwrap() = YldestState::Recording {path};
+                    },
+                }},
             
-            // on command switch to recording state
+            // on command switch (back) to recording state
             (YldestState::Connected{path},  
-            _, //Some(YldestCmd::Record), 
-            _ )
+            Some(YldestCmd::Record))
             => {*state.lock().unwrap() = YldestState::Recording {path: path.clone()}},  
 
-            // do recording when new data arrived
-            (YldestState::Recording{path:_}, 
-            _, 
-            Some(measure))
-            => {
-                write_buffer.push_str(&measure.to_csv_line());
-                if write_buffer.len() > 1000 {                    
-                    locked_file
-                        .lock().unwrap().as_ref().unwrap()
-                        .write_all(write_buffer.as_bytes()).unwrap();
-                    write_buffer.clear();
-                }},
-            
-            // pause recording on command
+            // pause recording on command, after writing out and syncing what was queued
             (YldestState::Recording{path}, 
-            Some(YldestCmd::Pause), 
-            _) 
+            Some(YldestCmd::Pause)) 
             => {
-                *state.lock().unwrap() = YldestState::Connected{path};
+                match write_queued(&locked_sink, &incoming, &mut meta)
+                    .and_then(|_| sync(&locked_sink, &locked_marks)) {
+                    Ok(()) => *state.lock().unwrap() = YldestState::Connected{path},
+                    Err(e) => fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, write_error(e)),
+                }
             },
 
-            // stop recording on command, keep path
-            (YldestState::Recording{path:_}, Some(YldestCmd::Stop), _) 
+            // write out and sync on command, keep recording
+            (YldestState::Recording{..} | YldestState::Connected{..}, Some(YldestCmd::Flush))
+            => {
+                let recording = matches!(*state.lock().unwrap(), YldestState::Recording{..});
+                let queued = match recording {
+                    true => write_queued(&locked_sink, &incoming, &mut meta),
+                    false => Ok(()),
+                };
+                if let Err(e) = queued.and_then(|_| sync(&locked_sink, &locked_marks)) {
+                    fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, write_error(e));
+                }
+            },
+
+            // stop recording (or pause) on command, keep path
+            // write out what is still buffered or queued, then close the file
+            (YldestState::Recording{path} | YldestState::Connected{path}, Some(YldestCmd::Stop))
                 => {
+                    if let Err(e) = write_queued(&locked_sink, &incoming, &mut meta) {
+                        fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, write_error(e));
+                        continue;
+                    }
+                    let sink = locked_sink.lock().unwrap().take();
+                    if let Some(file) = locked_marks.lock().unwrap().take() {
+                        if let Err(e) = file.sync_all() {
+                            eprintln!("Could not sync markers: {}", e);
+                        }
+                    }
+                    meta.finish(quality.lock().unwrap().since(&quality_start));
+                    // the final metadata also goes into the file, where the format has room
+                    if let Some(Err(e)) = sink.map(|sink| sink.finish(&meta)) {
+                        fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, write_error(e));
+                        continue;
+                    }
+                    if let Err(e) = meta.write(&path) {
+                        eprintln!("Could not write metadata of {:?}: {}", path, e);
+                    }
                     *state.lock().unwrap() = YldestState::Idle{dir: locked_dir.lock().unwrap().clone()};
                 },
+
+            // dismiss a failure on command
+            (YldestState::Failed{dir, ..}, Some(YldestCmd::Stop))
+            => {*state.lock().unwrap() = YldestState::Idle{dir}},
             
             _ => {},
         }
+        // do recording when new data arrived, whatever the command was
+        // samples arriving while not recording are dropped
+        if let Ok(sample) = incoming.try_recv() {
+            if matches!(*state.lock().unwrap(), YldestState::Recording{..}) {
+                let written = match locked_sink.lock().unwrap().as_mut() {
+                    Some(sink) => write_sample(sink, &sample, &mut meta),
+                    None => Ok(()),
+                };
+                if let Err(e) = written {
+                    fail(&state, &locked_sink, &locked_marks, &locked_dir, &meta, write_error(e));
+                }
+            }
+        }
+    }
+}
+
+/// Closing what can be closed after an error, then going to Failed
+///
+/// The sidecar keeps no stop time, so the recording is repaired
+/// at the next start (see `recovery`).
+fn fail(state: &LockedState, locked_sink: &LockedSink, locked_marks: &LockedFile, locked_dir: &LockedPath, meta: &Meta, error: YldestError) {
+    eprintln!("Recording failed: {}", error);
+    if let Some(sink) = locked_sink.lock().unwrap().take() {
+        if let Err(e) = sink.finish(meta) {
+            eprintln!("Could not close recording: {}", e);
+        }
+    }
+    *locked_marks.lock().unwrap() = None;
+    *state.lock().unwrap() = YldestState::Failed {
+        dir: locked_dir.lock().unwrap().clone(),
+        reason: error.to_string(),
+    };
+}
+
+/// Writing all that is queued
+fn write_queued(locked_sink: &LockedSink, incoming: &mpsc::Receiver<Vec<Yld>>, meta: &mut Meta) -> io::Result<()> {
+    if let Some(sink) = locked_sink.lock().unwrap().as_mut() {
+        for sample in incoming.try_iter() {
+            write_sample(sink, &sample, meta)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writing the rows of a sample, counting them
+fn write_sample(sink: &mut Box<dyn Sink>, sample: &[Yld], meta: &mut Meta) -> io::Result<()> {
+    for measure in sample {
+        sink.write(measure)?;
+        meta.rows += 1;
+    }
+    Ok(())
+}
+
+/// Syncing recording and markers to disk
+///
+/// Markers are not worth failing the recording for.
+fn sync(locked_sink: &LockedSink, locked_marks: &LockedFile) -> io::Result<()> {
+    if let Some(file) = locked_marks.lock().unwrap().as_ref() {
+        if let Err(e) = file.sync_data() {
+            eprintln!("Could not sync markers: {}", e);
+        }
+    }
+    match locked_sink.lock().unwrap().as_mut() {
+        Some(sink) => sink.sync(),
+        None => Ok(()),
     }
 }
@@ -0,0 +1,275 @@
+/// Reading Yld files
+///
+/// Recordings are long CSV files with one value per row:
+/// time, dev, sensory, chan and value.
+/// For viewing, rows are regrouped into Ytf8 samples
+/// and split by sensory into Banks, just like live data.
+/// Compressed files (`.gz`, `.zst`) are read like uncompressed ones,
+/// segmented recordings through their manifest.
+use crate::marks::{marks_path, Mark};
+use crate::segments::{is_manifest, Manifest};
+use crate::sinks::{
+    for_each_parquet_row, from_wide_line, is_finished_parquet, uncompressed, Compression, Format,
+};
+use crate::ylab::data::*;
+use egui::util::History;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Regrouping Yld rows into Ytf8 samples
+///
+/// Consecutive rows with the same time, device and sensory
+/// form one sample, the channel is the position in the sample.
+pub fn regroup(ylds: impl Iterator<Item = Yld>) -> Vec<Ytf8> {
+    let mut out: Vec<Ytf8> = Vec::new();
+    let mut current: Option<Ytf8> = None;
+    for yld in ylds {
+        out.extend(regroup_row(&mut current, yld));
+    }
+    out.extend(current);
+    out
+}
+
+/// Adding a row to the sample being regrouped
+///
+/// Returns the previous sample, once a row starts the next.
+fn regroup_row(current: &mut Option<Ytf8>, yld: Yld) -> Option<Ytf8> {
+    match current.as_mut() {
+        Some(sample)
+            if sample.time == yld.time && sample.dev == yld.dev && sample.sensory == yld.sensory =>
+        {
+            sample.read[yld.chan as usize] = yld.value;
+            None
+        }
+        _ => {
+            let mut sample = Ytf8 {
+                dev: yld.dev,
+                sensory: yld.sensory,
+                time: yld.time,
+                ..Default::default()
+            };
+            sample.read[yld.chan as usize] = yld.value;
+            current.replace(sample)
+        }
+    }
+}
+
+/// Splitting samples by sensory into Banks
+///
+/// The histories are unlimited in length and age,
+/// so they hold the whole recording, in order of time.
+pub fn to_banks(samples: &[Ytf8]) -> Banks {
+    let n_banks = samples.iter().map(|s| s.sensory as usize + 1).max().unwrap_or(0);
+    let mut banks: Banks = (0..n_banks)
+        .map(|_| History::new(0..usize::MAX, f32::INFINITY))
+        .collect();
+    let mut samples = samples.to_vec();
+    samples.sort_by_key(|sample| sample.time);
+    for sample in samples.iter() {
+        banks[sample.sensory as usize].add(sample.time.as_secs_f64(), *sample);
+    }
+    banks
+}
+
+/// Opening a text recording, decompressing by extension
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(match Compression::from_path(path) {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+    })
+}
+
+/// Passing the rows of a recording on, one by one
+///
+/// Recordings are Yld, wide CSV or Parquet, by extension (see `sinks::Format`),
+/// compressed or not, or the manifest of a segmented recording.
+/// A compressed file that ends early (e.g. after a crash) is read up to there,
+/// the broken end counts as one line that could not be parsed.
+/// So does the last segment of a segmented recording, if it cannot be read
+/// (a Parquet segment being written, or cut off by a crash).
+/// Returns the number of lines that could not be parsed.
+pub fn for_each_row(path: &Path, mut f: impl FnMut(Yld) -> io::Result<()>) -> io::Result<usize> {
+    if is_manifest(path) {
+        let manifest = Manifest::read(path)?;
+        let n_segments = manifest.segments.len();
+        let mut skipped = 0;
+        for (n, (segment, file)) in manifest.segments.iter().zip(manifest.paths(path)).enumerate() {
+            let unreadable = !file.is_file()
+                || (Format::from_path(&file) == Format::Parquet && !is_finished_parquet(&file));
+            if n + 1 == n_segments && !segment.complete && unreadable {
+                skipped += 1;
+                continue;
+            }
+            skipped += for_each_file_row(&file, &mut f)?;
+        }
+        return Ok(skipped);
+    }
+    for_each_file_row(path, &mut f)
+}
+
+/// Passing the rows of a single file on
+fn for_each_file_row(path: &Path, f: &mut impl FnMut(Yld) -> io::Result<()>) -> io::Result<usize> {
+    let format = Format::from_path(path);
+    if format == Format::Parquet {
+        return for_each_parquet_row(path, f);
+    }
+    let compressed = Compression::from_path(path) != Compression::None;
+    let mut skipped = 0;
+    for (n, line) in open(path)?.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) if compressed => {
+                skipped += 1;
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        match format {
+            Format::Wide if n == 0 && line.starts_with("time") => {}
+            Format::Wide => match from_wide_line(&line) {
+                Some(rows) => rows.into_iter().try_for_each(&mut *f)?,
+                None => skipped += 1,
+            },
+            _ => match Yld::from_csv_line(&line) {
+                Ok(yld) => f(yld)?,
+                Err(_) => skipped += 1,
+            },
+        }
+    }
+    Ok(skipped)
+}
+
+/// Reading all rows of a recording
+///
+/// Returns the rows and the number of lines that could not be parsed.
+pub fn read_rows(path: &Path) -> io::Result<(Vec<Yld>, usize)> {
+    let mut rows = Vec::new();
+    let skipped = for_each_row(path, |yld| {
+        rows.push(yld);
+        Ok(())
+    })?;
+    Ok((rows, skipped))
+}
+
+/// Reading all samples of a recording
+///
+/// + Ytf files (`.ytf`) hold Ytf8 lines as sent by the YLab
+/// + wide CSV (`.csv`) and Parquet files (`.parquet`) are read as Yld rows
+///   and regrouped
+/// + all other files are read as Yld and regrouped
+///
+/// Ytf, Yld and wide CSV may be compressed (e.g. `.ytf.gz`, `.yld.zst`).
+/// Returns the samples and the number of lines that could not be parsed.
+pub fn read_samples(path: &Path) -> io::Result<(Vec<Ytf8>, usize)> {
+    let mut samples = Vec::new();
+    let skipped = for_each_sample(path, |sample| {
+        samples.push(sample);
+        Ok(())
+    })?;
+    Ok((samples, skipped))
+}
+
+/// Passing the samples of a recording on, one by one
+///
+/// Files are read as in `read_samples`, without holding them in memory.
+/// Returns the number of lines that could not be parsed.
+pub fn for_each_sample(
+    path: &Path,
+    mut f: impl FnMut(Ytf8) -> io::Result<()>,
+) -> io::Result<usize> {
+    match uncompressed(path).extension() {
+        Some(ext) if ext == "ytf" => {
+            let mut skipped = 0;
+            for line in open(path)?.lines().map_while(Result::ok) {
+                match Ytf8::from_csv_line(&line) {
+                    Ok(sample) => f(sample)?,
+                    Err(_) => skipped += 1,
+                }
+            }
+            Ok(skipped)
+        }
+        _ => {
+            let mut current: Option<Ytf8> = None;
+            let skipped = for_each_row(path, |yld| match regroup_row(&mut current, yld) {
+                Some(sample) => f(sample),
+                None => Ok(()),
+            })?;
+            current.map_or(Ok(()), &mut f)?;
+            Ok(skipped)
+        }
+    }
+}
+
+/// Reading the markers of a recording, empty if there are none
+pub fn read_marks(recording: &Path) -> Vec<Mark> {
+    match File::open(marks_path(recording)) {
+        Err(_) => vec![],
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| Mark::from_csv_line(&line))
+            .collect(),
+    }
+}
+
+/// A recorded Yld file, opened for viewing
+pub struct YldFile {
+    pub path: PathBuf,
+    pub banks: Banks,
+    pub skipped: usize, // lines that are not Yld
+    pub marks: Vec<Mark>, // event markers, if the recording has them
+}
+
+impl YldFile {
+    /// Reading a Yld (or Ytf) file completely
+    ///
+    /// Lines that cannot be parsed (e.g. a truncated last line)
+    /// are skipped and counted.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let (samples, skipped) = read_samples(path)?;
+        Ok(YldFile {
+            path: path.to_path_buf(),
+            banks: to_banks(&samples),
+            skipped,
+            marks: read_marks(path),
+        })
+    }
+
+    pub fn n_banks(&self) -> u8 {
+        self.banks.len() as u8
+    }
+
+    /// Indices of the banks with samples
+    pub fn bank_indices(&self) -> Vec<u8> {
+        (0..self.n_banks())
+            .filter(|bank| !self.banks[*bank as usize].is_empty())
+            .collect()
+    }
+
+    /// First and last time stamp in the recording, in seconds
+    pub fn time_range(&self) -> Option<(f64, f64)> {
+        self.banks
+            .iter()
+            .filter_map(|bank| {
+                let (start, _) = bank.iter().next()?;
+                Some((start, start + bank.duration() as f64))
+            })
+            .reduce(|(s0, e0), (s1, e1)| (s0.min(s1), e0.max(e1)))
+    }
+
+    /// Samples of one bank within a time window
+    pub fn window(&self, bank: u8, from: f64, to: f64) -> Vec<(f64, Ytf8)> {
+        match self.banks.get(bank as usize) {
+            None => vec![],
+            Some(bank) => bank
+                .iter()
+                .skip_while(|(t, _)| *t < from)
+                .take_while(|(t, _)| *t <= to)
+                .collect(),
+        }
+    }
+}
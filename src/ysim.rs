@@ -0,0 +1,254 @@
+/// YLab Simulator
+///
+/// emits Ytf8 CSV lines (time, sensory, 8 values) like a YLab
+/// of a given version, at the rates of its sensories.
+/// Every bank has a pattern, which can be changed.
+/// Values are rounded to whole numbers, like raw readings.
+///
+/// The simulator is used for development without hardware:
+/// it is listed as port "Simulator" in the YLab thread.
+/// For tests, `lines_until` produces lines for any device time,
+/// without waiting.
+use crate::sources::{DataSource, Reading};
+use crate::ylab::data::Ytf8;
+use crate::ylab::{Sensory, YLabVersion};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Port name under which the simulator is listed
+pub const SIMULATOR_PORT: &str = "Simulator";
+
+/// Signal patterns for simulated banks
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Pattern {
+    /// sine wave, channels shifted in phase
+    Sine { hz: f64, amp: f64 },
+    /// white noise
+    Noise { amp: f64 },
+    /// EEG-like: waxing and waning 10 Hz alpha over background noise
+    Alpha { amp: f64 },
+    /// accelerometer and gyroscope (Yxz): gravity, slow sway and movement bursts
+    Acc,
+    /// buttons (MOI): one button is pressed every few seconds
+    Button,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Sine { hz, amp } => write!(f, "Sine {} Hz, {}", hz, amp),
+            Pattern::Noise { amp } => write!(f, "Noise {}", amp),
+            Pattern::Alpha { amp } => write!(f, "Alpha {}", amp),
+            Pattern::Acc => write!(f, "Accelerometer"),
+            Pattern::Button => write!(f, "Buttons"),
+        }
+    }
+}
+
+/// Patterns to choose from in the GUI
+pub const PATTERN_PRESETS: [Pattern; 6] = [
+    Pattern::Sine {
+        hz: 10.0,
+        amp: 1_000.0,
+    },
+    Pattern::Sine {
+        hz: 1.0,
+        amp: 1_000.0,
+    },
+    Pattern::Noise { amp: 1_000.0 },
+    Pattern::Alpha { amp: 2_000.0 },
+    Pattern::Acc,
+    Pattern::Button,
+];
+
+impl Pattern {
+    /// Default pattern for a sensory
+    pub fn default_for(sensory: Sensory) -> Self {
+        match sensory {
+            Sensory::Moi(_) => Pattern::Button,
+            Sensory::Adc(_, _) => Pattern::Alpha { amp: 2_000.0 },
+            Sensory::Yxz(_, _) => Pattern::Acc,
+            Sensory::Air(_) => Pattern::Sine {
+                hz: 0.02,
+                amp: 100.0,
+            },
+        }
+    }
+}
+
+/// Rates for sensories that are not sampled at a fixed rate
+const MOI_HZ: f64 = 10.0;
+const AIR_HZ: f64 = 1.0;
+
+/// A simulated bank
+struct SimBank {
+    sensory: Sensory,
+    pattern: Pattern,
+    hz: f64,
+    next: u64, // number of the next sample
+}
+
+/// Simulated YLab
+pub struct YSim {
+    banks: Vec<SimBank>,
+    rng: u64,                  // xorshift state
+    started: Instant,          // start of the device clock
+    background: [f64; 8],      // random walk for EEG background
+    pending: VecDeque<String>, // lines due, but not yet read
+}
+
+impl YSim {
+    /// Simulator for a YLab version, with default patterns
+    pub fn new(version: YLabVersion) -> Self {
+        let banks = version
+            .sensories()
+            .into_iter()
+            .map(|sensory| SimBank {
+                sensory,
+                pattern: Pattern::default_for(sensory),
+                hz: match sensory {
+                    Sensory::Moi(_) => MOI_HZ,
+                    Sensory::Air(_) => AIR_HZ,
+                    _ => sensory.hz().unwrap_or(1) as f64,
+                },
+                next: 0,
+            })
+            .collect();
+        YSim {
+            banks,
+            rng: 0x5EED_1AB5,
+            started: Instant::now(),
+            background: [0.0; 8],
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Changing the pattern of a bank
+    pub fn with_pattern(mut self, bank: u8, pattern: Pattern) -> Self {
+        if let Some(bank) = self.banks.get_mut(bank as usize) {
+            bank.pattern = pattern;
+        }
+        self
+    }
+
+    /// Lines that are due on the wall clock since creation
+    pub fn poll(&mut self) -> Vec<String> {
+        self.lines_until(self.started.elapsed())
+    }
+
+    /// Lines of all banks up to a device time, in order of time
+    pub fn lines_until(&mut self, device_time: Duration) -> Vec<String> {
+        self.samples_until(device_time)
+            .iter()
+            .map(|sample| sample.to_csv_line())
+            .collect()
+    }
+
+    /// Samples of all banks up to a device time, in order of time
+    pub fn samples_until(&mut self, device_time: Duration) -> Vec<Ytf8> {
+        let until = device_time.as_secs_f64();
+        let mut out = Vec::new();
+        for bank in 0..self.banks.len() {
+            loop {
+                let t = self.banks[bank].next as f64 / self.banks[bank].hz;
+                if t > until {
+                    break;
+                }
+                let read = self.read(bank, t);
+                out.push(Ytf8 {
+                    dev: 1,
+                    sensory: bank as u8,
+                    time: Duration::from_millis((t * 1000.0) as u64),
+                    read,
+                });
+                self.banks[bank].next += 1;
+            }
+        }
+        out.sort_by_key(|sample| sample.time);
+        out
+    }
+
+    /// Uniform random number in [-1, 1]
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// Readings of one bank at time t (in seconds)
+    fn read(&mut self, bank: usize, t: f64) -> [f64; 8] {
+        let n_chan = self.banks[bank].sensory.n_chan().min(8) as usize;
+        let mut read = [0.0; 8];
+        match self.banks[bank].pattern {
+            Pattern::Sine { hz, amp } => {
+                for (chan, value) in read.iter_mut().enumerate().take(n_chan) {
+                    let phase = chan as f64 * PI / 4.0;
+                    *value = amp * (2.0 * PI * hz * t + phase).sin();
+                }
+            }
+            Pattern::Noise { amp } => {
+                for value in read.iter_mut().take(n_chan) {
+                    *value = amp * self.uniform();
+                }
+            }
+            Pattern::Alpha { amp } => {
+                // alpha waxes and wanes over about ten seconds
+                let envelope = 0.5 + 0.5 * (2.0 * PI * 0.1 * t).sin();
+                for (chan, value) in read.iter_mut().enumerate().take(n_chan) {
+                    self.background[chan] = 0.98 * self.background[chan] + self.uniform();
+                    let alpha = (2.0 * PI * 10.0 * t + chan as f64).sin();
+                    *value = amp * (envelope * alpha + 0.1 * self.background[chan]);
+                }
+            }
+            Pattern::Acc => {
+                // 1 g = 16384, sway at 0.3 Hz, short movements every 5 seconds
+                const G: f64 = 16_384.0;
+                let sway = 0.1 * (2.0 * PI * 0.3 * t).sin();
+                let moving = (t % 5.0) < 0.5;
+                let burst = if moving { 0.3 } else { 0.0 };
+                let acc = [G * sway, G * (sway / 2.0), G * (1.0 - sway.abs())];
+                let gyro = [
+                    500.0 * (2.0 * PI * 0.3 * t).cos(),
+                    250.0 * (2.0 * PI * 0.3 * t).cos(),
+                    0.0,
+                ];
+                for chan in 0..n_chan.min(6) {
+                    let base = if chan < 3 { acc[chan] } else { gyro[chan - 3] };
+                    read[chan] = base + G * (burst + 0.01) * self.uniform();
+                }
+            }
+            Pattern::Button => {
+                // every 4 seconds a button is pressed for half a second
+                let press = (t / 4.0) as u64;
+                if t % 4.0 < 0.5 && n_chan > 0 {
+                    read[(press % n_chan as u64) as usize] = 1.0;
+                }
+            }
+        }
+        read.map(|r| r.round())
+    }
+}
+
+/// The simulator as a source, its lines go through the Ytf8 parser
+impl DataSource for YSim {
+    fn name(&self) -> String {
+        SIMULATOR_PORT.to_string()
+    }
+
+    fn next_sample(&mut self) -> Reading {
+        if self.pending.is_empty() {
+            let lines = self.poll();
+            self.pending.extend(lines);
+        }
+        match self.pending.pop_front() {
+            None => Reading::Empty,
+            Some(line) => match Ytf8::from_csv_line(&line) {
+                Ok(sample) => Reading::Sample(sample),
+                Err(e) => Reading::Invalid(format!("Not Ytf8: {:?}", e)),
+            },
+        }
+    }
+}
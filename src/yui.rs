@@ -0,0 +1,1409 @@
+pub use eframe::egui;
+// use egui::load::LoadError;
+pub use egui::util::History;
+use egui::Ui;
+// use egui_plot::Plot;
+pub use ystudio::dsp;
+pub use ystudio::ylab::*;
+pub use ystudio::ylab::{data::*, YLabCmd, YLabState, YLabVersion};
+pub use ystudio::yldest::*;
+pub use ystudio::yldfile::*;
+pub use ystudio::marks::{Mark, Marks};
+pub use ystudio::meta::Meta;
+pub use ystudio::quality::Quality;
+use ystudio::segments::{self, Rotation};
+use ystudio::sinks;
+pub use ystudio::sinks::{Compression, Format, COMPRESSIONS, FORMATS};
+pub use ystudio::ysim::{Pattern, PATTERN_PRESETS, SIMULATOR_PORT};
+pub use egui_plot::PlotPoints;
+pub use std::sync::mpsc::Sender;
+pub use std::{sync::*, thread};
+
+impl eframe::App for Ystudio {
+    /// Called by the frame work to save state before shutdown.
+    /// Note that you must enable the `persistence` feature for this to work.
+
+    /*//#[cfg(feature = "persistence")]
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }*/
+
+    /// Called each time the UI needs repainting, which may be many times per second.
+    /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // update_left_panel(ctx, self);
+        update_right_panel(ctx, self);
+        update_file_dialog(ctx, self);
+        update_mark_hotkeys(ctx, self);
+        update_central_panel(ctx, self);
+        update_bottom_panel(ctx, self);
+        ctx.request_repaint();
+    }
+
+    /// Called when the window closes: a running recording is stopped,
+    /// so that it is complete on disk
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if matches!(
+            *self.yldest_state.lock().unwrap(),
+            YldestState::Idle { .. } | YldestState::Failed { .. }
+        ) {
+            return;
+        }
+        self.yldest_cmd.send(YldestCmd::Stop).unwrap();
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_secs(5) {
+            if matches!(*self.yldest_state.lock().unwrap(), YldestState::Idle { .. }) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        eprintln!("Recording did not stop in time");
+    }
+}
+
+/// The ystudio object contains thread-safe channels
+/// for communication between components, as well as
+/// ui properties
+///
+/// + ylab_state, which is a mutexed YLabState
+/// + ylab_cmd for sending commands to YLab, esp. changing states
+/// + yldest_state carries the state of the storage component
+/// + yldest_cmd for controlling the storage component
+/// + yld_wind, which is a egui History of YLab Samples in Yld format
+/// + ytf_wind, which is a egui History of samples in Ytf8 format
+/// + quality, counters of gaps, duplicates etc. per sensory
+/// + marks for setting event markers
+/// + ui, which captures UI related variables with one global lock
+/// + file_dialog and offline for viewing recorded Yld files
+
+#[derive(Clone)]
+pub struct Ystudio {
+    pub ylab_state: Arc<Mutex<YLabState>>,     // shared state
+    pub ylab_cmd: Sender<YLabCmd>,             // sending commands to ylab
+    pub yldest_state: Arc<Mutex<YldestState>>, // shared state
+    pub yldest_cmd: mpsc::Sender<YldestCmd>,   // sending commands to control storage
+    pub yld_wind: Arc<Mutex<History<Yld>>>,    // data stream, sort of temporal vecdeque
+    pub ytf_wind: Arc<Mutex<Banks>>, // data stream, sort of temporal vecdeque, one per sensory
+    pub quality: Arc<Mutex<Quality>>, // data quality counters per sensory
+    pub marks: Marks,                 // setting event markers
+    pub ui: Arc<Mutex<Yui>>,         // ui parameters with outer lock, more convenient
+    pub file_dialog: Arc<Mutex<Option<(FileChoice, FileDialog)>>>, // dialog for opening a recording
+    pub offline: Arc<Mutex<Option<YldFile>>>,        // recording opened for viewing
+}
+
+/// Data for the UI
+///
+///
+/// Structure holding the parameters of the ui
+///
+/// such as:
+/// 1.  serial port connected to YLab
+/// 1.  selecting channels for display
+/// 1.  keeping filter controls in the safe range of the underlying algorithm.
+///     The upper frequency limit is always given by the *Nyquist* frequency. But for the lower limit, it differs.
+///
+/// For *low pass filters* zero is a possible lower limit, effectively switching the filter off. However, when
+/// passing through the range (1, 0), wavelengths become very long. Apparently, the used algorithm
+///
+/// , and the value buffer becomes the limiting factor.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataView {
+    None,
+    Log,
+    Plot,
+    PlotFft,
+}
+
+/// What a file from the file dialog is used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileChoice {
+    View,
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub struct Yui {
+    pub selected_port: Option<String>,
+    pub selected_version: Option<YLabVersion>,
+    pub detect_version: bool, // version detected on connect, instead of selected
+    pub selected_bank: u8,
+    pub selected_channels: [bool; 8],
+    pub view: DataView,
+    pub lowpass_threshold: f64,
+    pub fft_min: f64,
+    pub fft_max: f64,
+    pub offline_range: Option<(f64, f64)>, // visible time range of an opened recording
+    pub replay_path: Option<std::path::PathBuf>,
+    pub replay_speed: f64,
+    pub replay_loop: bool,
+    pub sim_patterns: Vec<Pattern>, // one per bank of the simulator
+    pub participant: String,        // metadata of the next recording
+    pub session: String,
+    pub notes: String,
+    pub mark_label: String, // label of the next marker
+    pub rec_format: Format, // file format of the next recording
+    pub rec_compression: Compression, // of Yld and wide CSV recordings
+    pub rec_rotation: Rotation,       // splitting long recordings into segments
+}
+
+/// Initializing the egui window
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub fn egui_init(ystud: Ystudio) {
+    let options = eframe::NativeOptions {
+        // transparent: true,
+        // initial_window_size: Some(egui::vec2(1000.0, 800.0)),
+        // resizable: true,
+        ..Default::default()
+    };
+    //egui_logger::builder().init().unwrap();
+    eframe::run_native(
+        "Ystudio Zero", // unused title
+        options,
+        Box::new(|_cc| Ok(Box::new(ystud))),
+    )
+    .unwrap();
+}
+
+use egui::ecolor::Color32;
+const LINE_COLORS: [Color32; 8] = [
+    Color32::BLACK,
+    Color32::BLUE,
+    Color32::GREEN,
+    Color32::RED,
+    Color32::GRAY,
+    Color32::DARK_BLUE,
+    Color32::DARK_GREEN,
+    Color32::DARK_RED,
+];
+
+/// YLAB CONTROL in the right panel
+/// + Connecting; reading and disconnecting
+///
+pub fn update_right_panel(ctx: &egui::Context, ystud: &mut Ystudio) {
+    egui::SidePanel::right("left_right_panel").show(ctx, |ui| {
+        let mut ui_state = ystud.ui.lock().unwrap();
+        let ylab_state = ystud.ylab_state.lock().unwrap();
+        // setting defaults
+        let selected_version = match ui_state.selected_version.clone() {
+            Some(version) => version,
+            None => YLabVersion::Go,
+        };
+
+        match ylab_state.clone() {
+            // Selecting port and YLab version
+            // When both are selected, the connect button is shown
+            YLabState::Disconnected { ports } => {
+                ui.heading("Disconnected");
+                // When ports are available, show the options
+                match ports {
+                    None => {
+                        ui.label("Searching ports");
+                        eprintln!("No ports available");
+                    }
+                    Some(ports) => {
+                        // unpacking version and port
+                        let selected_port: Option<String> = match ui_state.selected_port.clone() {
+                            // in case there is a user-selected port, use it
+                            Some(port) => Some(port),
+                            // otherwise use the first available port
+                            None => {
+                                if ports.len() > 0 {
+                                    Some(ports[0].name.clone())
+                                } else {
+                                    None
+                                }
+                            }
+                        };
+
+                        // one selectable label for each port
+                        ui.label("Available Ports");
+                        for i in ports.iter() {
+                            // Create a selectable label for each port,
+                            // naming the device where USB tells
+                            if ui
+                                .add(egui::SelectableLabel::new(
+                                    selected_port.as_ref() == Some(&i.name),
+                                    i.to_string(),
+                                ))
+                                .clicked()
+                            {
+                                ui_state.selected_port = Some(i.name.clone())
+                            }
+                        }
+                        ui.separator();
+
+                        // one selectable per version, or detecting it
+                        ui.label("Version");
+                        let shown_version = (!ui_state.detect_version).then_some(selected_version);
+                        if ui
+                            .add(egui::SelectableLabel::new(ui_state.detect_version, "Auto"))
+                            .on_hover_text("Detect the version from the data")
+                            .clicked()
+                        {
+                            ui_state.detect_version = true;
+                        }
+                        if ui
+                            .add(egui::SelectableLabel::new(
+                                shown_version == Some(YLabVersion::Zet),
+                                "Zet",
+                            ))
+                            .clicked()
+                        {
+                            ui_state.selected_version = Some(YLabVersion::Zet);
+                            ui_state.detect_version = false;
+                        }
+                        if ui
+                            .add(egui::SelectableLabel::new(
+                                shown_version == Some(YLabVersion::Pro),
+                                "Pro",
+                            ))
+                            .clicked()
+                        {
+                            ui_state.selected_version = Some(YLabVersion::Pro);
+                            ui_state.detect_version = false;
+                        }
+                        if ui
+                            .add(egui::SelectableLabel::new(
+                                shown_version == Some(YLabVersion::Go),
+                                "Go",
+                            ))
+                            .clicked()
+                        {
+                            ui_state.selected_version = Some(YLabVersion::Go);
+                            ui_state.detect_version = false;
+                        }
+                        if ui
+                            .add(egui::SelectableLabel::new(
+                                shown_version == Some(YLabVersion::GoMotion(4)),
+                                "Go Motion 4",
+                            ))
+                            .clicked()
+                        {
+                            ui_state.selected_version = Some(YLabVersion::GoMotion(4));
+                            ui_state.detect_version = false;
+                        }
+                        if ui
+                            .add(egui::SelectableLabel::new(
+                                shown_version == Some(YLabVersion::GoStress),
+                                "Go Stress",
+                            ))
+                            .clicked()
+                        {
+                            ui_state.selected_version = Some(YLabVersion::GoStress);
+                            ui_state.detect_version = false;
+                        }
+                        if ui
+                            .add(egui::SelectableLabel::new(
+                                shown_version == Some(YLabVersion::Mini),
+                                "Mini",
+                            ))
+                            .clicked()
+                        {
+                            ui_state.selected_version = Some(YLabVersion::Mini);
+                            ui_state.detect_version = false;
+                        }
+                        ui.separator();
+                        // The button is only shown when version and port are selected (which currently is by default).
+                        // It commits the connection command to the YLab thread.
+                        //match ( ystud.ui.selected_version.lock().unwrap().clone(), ystud.ui.selected_port.lock().unwrap().clone())  {
+                        match (ui_state.selected_version, ui_state.selected_port.clone()) {
+                            (Some(version), Some(port)) if port == SIMULATOR_PORT => {
+                                update_sim_controls(ui, ystud, &mut ui_state, version);
+                            }
+                            (_, Some(port)) if ui_state.detect_version && port != SIMULATOR_PORT => {
+                                if ui
+                                    .button("Connect")
+                                    .on_hover_text("Connect to YLab and detect its version")
+                                    .clicked()
+                                {
+                                    ystud
+                                        .ylab_cmd
+                                        .send(YLabCmd::Detect { port_name: port })
+                                        .unwrap();
+                                }
+                            }
+                            (Some(version), Some(port)) => {
+                                if ui
+                                    .button("Connect")
+                                    .on_hover_text("Connect to YLab")
+                                    .clicked()
+                                {
+                                    ystud
+                                        .ylab_cmd
+                                        .send(YLabCmd::Connect {
+                                            version: version,
+                                            port_name: port.to_string(),
+                                        })
+                                        .unwrap();
+                                }
+                            }
+                            _ => {
+                                ui.label("Select port and version");
+                            }
+                        }
+                        // Replaying a recording instead of a YLab
+                        ui.separator();
+                        update_replay_controls(ui, ystud, &mut ui_state, selected_version);
+                    }
+                }
+                // Viewing recordings while no YLab is connected
+                ui.separator();
+                update_offline_controls(ui, ystud, &mut ui_state);
+            }
+            // Watching the first seconds of data for the version
+            YLabState::Detecting { port_name } => {
+                ui.heading("Detecting");
+                ui.label(port_name);
+                ui.spinner();
+                if ui
+                    .button("Disconnect")
+                    .on_hover_text("Stop detecting")
+                    .clicked()
+                {
+                    ystud.ylab_cmd.send(YLabCmd::Disconnect {}).unwrap();
+                }
+            }
+            // The YLab has gone, waiting for it to come back
+            YLabState::Reconnecting {
+                version,
+                port_name,
+                reason,
+            } => {
+                ui.heading("Reconnecting");
+                ui.label(format!("{}:{}", version, port_name));
+                ui.colored_label(ui.visuals().warn_fg_color, reason);
+                ui.label("Recording goes on when the YLab is back");
+                if ui
+                    .button("Disconnect")
+                    .on_hover_text("Stop waiting for the YLab")
+                    .clicked()
+                {
+                    ystud.ylab_cmd.send(YLabCmd::Disconnect {}).unwrap();
+                    // a running recording goes to disk
+                    ystud.yldest_cmd.send(YldestCmd::Flush).unwrap();
+                }
+            }
+            // Connecting or reading went wrong, showing why
+            // Retry connects to the same port again
+            YLabState::Failed {
+                version,
+                port_name,
+                reason,
+            } => {
+                ui.heading("Failed");
+                ui.label(format!("{}:{}", version, port_name));
+                ui.colored_label(ui.visuals().error_fg_color, reason);
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Retry")
+                        .on_hover_text("Connect to the same port again")
+                        .clicked()
+                    {
+                        ystud
+                            .ylab_cmd
+                            .send(YLabCmd::Connect { version, port_name })
+                            .unwrap();
+                    }
+                    if ui
+                        .button("Dismiss")
+                        .on_hover_text("Back to port selection")
+                        .clicked()
+                    {
+                        ystud.ylab_cmd.send(YLabCmd::Disconnect {}).unwrap();
+                    }
+                });
+                // or replaying another recording instead
+                ui.separator();
+                update_replay_controls(ui, ystud, &mut ui_state, selected_version);
+            }
+            // Connected to YLab by selecting port and version
+            YLabState::Connected { version, port_name } => {
+                ui.heading("Connected");
+                ui.label(format!("{}:{}", version, port_name));
+                if ui
+                    .button("Disconnect")
+                    .on_hover_text("Disconnect YLab")
+                    .clicked()
+                {
+                    ystud.ylab_cmd.send(YLabCmd::Disconnect {}).unwrap();
+                }
+                if ui.button("Read").on_hover_text("Read from YLab").clicked() {
+                    ystud.ylab_cmd.send(YLabCmd::Read {}).unwrap();
+                }
+            }
+
+            // Reading from YLab, showing the port, version and sample rate
+            YLabState::Reading { version, port_name } => {
+                // a detected version becomes the selected one
+                if ui_state.detect_version {
+                    ui_state.selected_version = Some(version);
+                }
+                // Get incoming data
+                let yld_wind = ystud.yld_wind.lock().unwrap();
+                // Info header
+                ui.heading("Reading");
+                ui.label(format!("{}:{}", version, port_name));
+                // Disconnect
+                if ui
+                    .button("Disconnect")
+                    .on_hover_text("Disconnects YLab")
+                    .clicked()
+                {
+                    ystud.ylab_cmd.send(YLabCmd::Disconnect {}).unwrap();
+                    // a running recording goes to disk
+                    ystud.yldest_cmd.send(YldestCmd::Flush).unwrap();
+                    ui_state.view = DataView::None;
+                    //println!("Cmd: Stop")
+                };
+                ui.separator();
+
+                // View, sensory and channels
+                let banks: Vec<u8> = (1..=version.n_banks()).collect();
+                update_view_controls(ui, &mut ui_state, &banks);
+
+                if ui_state.selected_bank > 0 {
+                    let buffer_size = yld_wind.len() / 8;
+
+                    /*
+                    // Check for buffer under-run
+                    if buffer_size < version.fft_size() {
+                        ui.label("still buffering");
+                        if ui.button("Stop Reading").on_hover_text("Stop reading").clicked(){
+                            ystud.ylab_cmd.send(YLabCmd::Stop {}).unwrap();
+                            println!("Cmd: Stop")};
+                        return;
+                    }
+                     */
+
+                    let duration = yld_wind.duration() as f64; // <------ Should be based on YTF stream
+                    let sample_rate = buffer_size as f64 / duration;
+                    let nyquist = sample_rate / 2.;
+                    let low_limit = duration / 2.;
+                    ui.separator();
+                    ui.label(format!("{} Hz per channel", sample_rate as usize));
+                    ui.separator();
+
+                    update_lowpass_control(ui, &mut ui_state, low_limit, nyquist);
+
+                    // ui.separator();
+                    // ui.heading("FFT");
+                    // // Sliders for FFT range
+                    // ui.label("min (Hz)");
+                    // let min_range = 0. ..=(nyquist - 5.);
+                    // let fft_min_slider
+                    //     = egui::widgets::Slider::new(&mut ui_state.fft_min, min_range)
+                    //     //.clamping(true)
+                    //     .logarithmic(true)
+                    //     .fixed_decimals(3);
+                    // ui.add(fft_min_slider);
+
+                    // ui.label("max (Hz)");
+                    // let max_range = (ui_state.fft_min + 2.)..=(nyquist - 5.);
+                    // let fft_max_slider
+                    //     = egui::widgets::Slider::new(&mut ui_state.fft_max, max_range)
+                    //     //.clamp_to_range(true)
+                    //     .fixed_decimals(1);
+                    // ui.add(fft_max_slider);
+                    // ui.separator();
+                }
+
+                update_quality_view(ui, &ystud.quality.lock().unwrap(), version);
+                ui.separator();
+
+                update_recording_controls(ui, ystud, &mut ui_state, Some((version, &port_name)));
+                update_mark_controls(ui, ystud, &mut ui_state);
+            }
+        }
+        // a recording outlives its YLab, e.g. a failed one waits for Dismiss
+        if !matches!(*ylab_state, YLabState::Reading { .. }) {
+            update_recording_controls(ui, ystud, &mut ui_state, None);
+        }
+    });
+}
+
+/// RECORDING in the right panel
+/// + metadata, format and segments for a new recording, while reading
+/// + pause, resume and stop of a running recording, also when the YLab is gone
+/// + the reason of a failed recording
+pub fn update_recording_controls(
+    ui: &mut Ui,
+    ystud: &Ystudio,
+    ui_state: &mut Yui,
+    reading: Option<(YLabVersion, &str)>,
+) {
+    // Start or stop recording
+    // asking the state of recording thread
+    let yldest_state = ystud.yldest_state.lock().unwrap().clone();
+    match yldest_state {
+        // new recordings need a YLab that is read
+        YldestState::Idle { dir: Some(_dir) } => {
+            let Some((version, port_name)) = reading else {
+                return;
+            };
+            ui.heading("Recording");
+            ui.label("Idle");
+            // stored in the metadata of the recording
+            ui.horizontal(|ui| {
+                ui.label("Participant");
+                ui.text_edit_singleline(&mut ui_state.participant);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Session");
+                ui.text_edit_singleline(&mut ui_state.session);
+            });
+            ui.label("Notes");
+            ui.text_edit_multiline(&mut ui_state.notes);
+            egui::ComboBox::from_label("Format")
+                .selected_text(ui_state.rec_format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in FORMATS {
+                        ui.selectable_value(&mut ui_state.rec_format, format, format.to_string());
+                    }
+                });
+            // Parquet compresses by itself
+            ui.add_enabled_ui(ui_state.rec_format != Format::Parquet, |ui| {
+                egui::ComboBox::from_label("Compression")
+                    .selected_text(ui_state.rec_compression.to_string())
+                    .show_ui(ui, |ui| {
+                        for compression in COMPRESSIONS {
+                            ui.selectable_value(
+                                &mut ui_state.rec_compression,
+                                compression,
+                                compression.to_string(),
+                            );
+                        }
+                    });
+            });
+            // 0 for not splitting
+            let rotation = &mut ui_state.rec_rotation;
+            let mut minutes = rotation.max_duration.map_or(0, |d| d.as_secs() / 60);
+            let mut mb = rotation.max_bytes.map_or(0, |bytes| bytes / 1_000_000);
+            ui.horizontal(|ui| {
+                ui.label("Segments of");
+                ui.add(egui::DragValue::new(&mut minutes).suffix(" min"))
+                    .on_hover_text("Start a new file after this time, 0 for never");
+                ui.add(egui::DragValue::new(&mut mb).suffix(" MB"))
+                    .on_hover_text("Start a new file after this size, 0 for never");
+            });
+            rotation.max_duration =
+                (minutes > 0).then(|| std::time::Duration::from_secs(minutes * 60));
+            rotation.max_bytes = (mb > 0).then_some(mb * 1_000_000);
+            if ui
+                .button("New Rec")
+                .on_hover_text("Start a new recording")
+                .clicked()
+            {
+                let dir = std::env::current_dir().unwrap();
+                let mut meta = Meta::new(version, port_name);
+                meta.participant = ui_state.participant.clone();
+                meta.session = ui_state.session.clone();
+                meta.notes = ui_state.notes.clone();
+                ystud
+                    .yldest_cmd
+                    .send(YldestCmd::New {
+                        change_dir: Some(dir),
+                        file_name: None,
+                        format: ui_state.rec_format,
+                        compression: ui_state.rec_compression,
+                        rotation: ui_state.rec_rotation,
+                        meta: Box::new(meta),
+                    })
+                    .unwrap()
+            }
+        }
+        YldestState::Recording { path } => {
+            ui.heading("Recording");
+            ui.heading("Recording");
+            ui.label(format!("Recording to {}", path.to_str().unwrap()));
+            if ui
+                .button("Pause")
+                .on_hover_text("Pause recording, the file stays open")
+                .clicked()
+            {
+                ystud.yldest_cmd.send(YldestCmd::Pause).unwrap();
+            }
+            if ui
+                .button("Stop Rec")
+                .on_hover_text("Stop recording")
+                .clicked()
+            {
+                ystud.yldest_cmd.send(YldestCmd::Stop).unwrap();
+            }
+        }
+        YldestState::Connected { path } => {
+            ui.heading("Recording");
+            ui.heading("Recording");
+            ui.label(format!("Paused {}", path.to_str().unwrap()));
+            if ui
+                .button("Resume")
+                .on_hover_text("Continue recording to the same file")
+                .clicked()
+            {
+                ystud.yldest_cmd.send(YldestCmd::Record).unwrap();
+            }
+            if ui
+                .button("Stop Rec")
+                .on_hover_text("Stop recording")
+                .clicked()
+            {
+                ystud.yldest_cmd.send(YldestCmd::Stop).unwrap();
+            }
+        }
+        // what was written so far is closed, the reason is shown
+        YldestState::Failed { reason, .. } => {
+            ui.heading("Recording");
+            ui.heading("Recording");
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!("Recording failed: {}", reason),
+            );
+            if ui
+                .button("Dismiss")
+                .on_hover_text("Back to starting a new recording")
+                .clicked()
+            {
+                ystud.yldest_cmd.send(YldestCmd::Stop).unwrap();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Function keys F1 to F12 set markers labelled "F1" to "F12"
+const MARK_KEYS: [egui::Key; 12] = [
+    egui::Key::F1,
+    egui::Key::F2,
+    egui::Key::F3,
+    egui::Key::F4,
+    egui::Key::F5,
+    egui::Key::F6,
+    egui::Key::F7,
+    egui::Key::F8,
+    egui::Key::F9,
+    egui::Key::F10,
+    egui::Key::F11,
+    egui::Key::F12,
+];
+
+/// MARKERS in the right panel
+/// + text field and button for a labelled marker (Enter works, too)
+/// + the last marker set
+pub fn update_mark_controls(ui: &mut Ui, ystud: &Ystudio, ui_state: &mut Yui) {
+    ui.label("Marker (or F1 - F12)");
+    let response = ui.text_edit_singleline(&mut ui_state.mark_label);
+    let entered = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    if ui.button("Mark").on_hover_text("Set a marker now").clicked() || entered {
+        ystud.marks.mark(&ui_state.mark_label);
+    }
+    if let Some(last) = ystud.marks.recent().last() {
+        ui.label(format!("Last: {} at {:.3}s", last.label, last.time.as_secs_f64()));
+    }
+}
+
+/// Sets markers on function keys, while reading
+pub fn update_mark_hotkeys(ctx: &egui::Context, ystud: &Ystudio) {
+    if !matches!(*ystud.ylab_state.lock().unwrap(), YLabState::Reading { .. }) {
+        return;
+    }
+    for (n, key) in MARK_KEYS.iter().enumerate() {
+        if ctx.input(|i| i.key_pressed(*key)) {
+            ystud.marks.mark(&format!("F{}", n + 1));
+        }
+    }
+}
+
+/// Draws markers within a time range as vertical lines
+pub fn plot_marks(plot_ui: &mut egui_plot::PlotUi, marks: &[Mark], from: f64, to: f64) {
+    for mark in marks {
+        let time = mark.time.as_secs_f64();
+        if time >= from && time <= to {
+            plot_ui.vline(
+                egui_plot::VLine::new(time)
+                    .name(&mark.label)
+                    .color(Color32::GRAY),
+            );
+        }
+    }
+}
+
+/// QUALITY in the right panel
+/// + one line per sensory with problems found
+/// + lines that could not be read
+pub fn update_quality_view(ui: &mut Ui, quality: &Quality, version: YLabVersion) {
+    ui.heading("Quality");
+    let labels = version.bank_labels();
+    for (bank, counts) in quality.banks.iter().enumerate() {
+        let label = labels.get(bank).copied().unwrap_or("?");
+        let text = if counts.issues() == 0 {
+            format!("{}: ok ({} samples)", label, counts.samples)
+        } else {
+            format!(
+                "{}: {} gaps ({} lost), {} duplicates, {} out of order",
+                label, counts.gaps, counts.missing, counts.duplicates, counts.out_of_order
+            )
+        };
+        ui.label(text);
+    }
+    if quality.parse_errors > 0 {
+        ui.label(format!("{} unreadable lines", quality.parse_errors));
+    }
+}
+
+/// View controls, shared by live and offline data
+/// + view
+/// + sensory bank, one of `banks` (if more than one)
+/// + channels
+pub fn update_view_controls(ui: &mut Ui, ui_state: &mut Yui, banks: &[u8]) {
+    ui.label("View");
+    egui::ComboBox::from_label("select View")
+        .selected_text(format!("{:?}", ui_state.view))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut ui_state.view, DataView::PlotFft, "Plot + FFT");
+            ui.selectable_value(&mut ui_state.view, DataView::Plot, "Plot");
+            ui.selectable_value(&mut ui_state.view, DataView::Log, "Log");
+            ui.selectable_value(&mut ui_state.view, DataView::None, "None");
+        });
+    // Bank selector (if more than one)
+    if banks.len() > 1 {
+        ui.heading("Sensory");
+        let mut index = banks
+            .iter()
+            .position(|bank| *bank == ui_state.selected_bank)
+            .unwrap_or(0);
+        ui.add(
+            egui::Slider::new(&mut index, 0..=banks.len() - 1)
+                .custom_formatter(|index, _| banks[index as usize].to_string())
+                .text(""),
+        );
+        ui_state.selected_bank = banks[index];
+        ui.label(format!(
+            "Sensory {} of {}",
+            &ui_state.selected_bank,
+            banks[banks.len() - 1]
+        ));
+        ui.separator();
+    }
+
+    // Selecting channels to plot
+    ui.heading("Channels");
+    let selected_channels = ui_state.selected_channels;
+    for (chan, _label) in selected_channels.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut ui_state.selected_channels[chan], "");
+            // Set the color for the label
+            ui.label(egui::RichText::new(chan.to_string()).color(LINE_COLORS[chan]));
+        });
+    }
+}
+
+/// Slider for the low-pass filter on the raw signal
+pub fn update_lowpass_control(ui: &mut Ui, ui_state: &mut Yui, low_limit: f64, nyquist: f64) {
+    ui.heading("Raw signal");
+    ui.label("Low-pass filter (Hz)");
+    let lowpass_slider =
+        egui::widgets::Slider::new(&mut ui_state.lowpass_threshold, low_limit..=nyquist)
+            .logarithmic(true)
+            .fixed_decimals(3);
+    ui.add(lowpass_slider);
+}
+
+/// OFFLINE VIEWING in the right panel
+/// + opening and closing a recorded Yld file
+/// + same view controls as for live data
+pub fn update_offline_controls(ui: &mut Ui, ystud: &Ystudio, ui_state: &mut Yui) {
+    ui.heading("Offline");
+    let mut offline = ystud.offline.lock().unwrap();
+    match offline.as_ref() {
+        None => {
+            if ui
+                .button("Open Yld")
+                .on_hover_text("View a recorded Yld file")
+                .clicked()
+            {
+                open_file_dialog(ystud, FileChoice::View);
+            }
+        }
+        Some(file) => {
+            let name = file
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            ui.label(name);
+            if file.skipped > 0 {
+                ui.label(format!("{} lines skipped", file.skipped));
+            }
+            // only banks with samples, e.g. bank 0 is the ADC of a Mini
+            let banks = file.bank_indices();
+            if ui.button("Close").on_hover_text("Close the recording").clicked() {
+                *offline = None;
+                return;
+            }
+            ui.separator();
+            if !banks.contains(&ui_state.selected_bank) {
+                ui_state.selected_bank = banks.first().copied().unwrap_or(0);
+            }
+            update_view_controls(ui, ui_state, &banks);
+            // filter range from the visible part of the recording
+            let samples = match ui_state.offline_range {
+                Some((from, to)) => file.window(ui_state.selected_bank, from, to),
+                None => vec![],
+            };
+            if let (Some((first, _)), Some((last, _))) = (samples.first(), samples.last()) {
+                let duration = last - first;
+                if duration > 0. {
+                    let sample_rate = samples.len() as f64 / duration;
+                    ui.separator();
+                    ui.label(format!("{} Hz per channel", sample_rate as usize));
+                    ui.separator();
+                    update_lowpass_control(ui, ui_state, duration / 2., sample_rate / 2.);
+                }
+            }
+        }
+    }
+}
+
+/// SIMULATOR in the right panel
+/// + one pattern per bank of the selected version
+/// + connecting to the simulator
+pub fn update_sim_controls(
+    ui: &mut Ui,
+    ystud: &Ystudio,
+    ui_state: &mut Yui,
+    version: YLabVersion,
+) {
+    let sensories = version.sensories();
+    // a new version starts with its default patterns
+    if ui_state.sim_patterns.len() != sensories.len() {
+        ui_state.sim_patterns = sensories.iter().map(|s| Pattern::default_for(*s)).collect();
+    }
+    for (bank, pattern) in ui_state.sim_patterns.iter_mut().enumerate() {
+        egui::ComboBox::from_label(format!("Bank {}", bank))
+            .selected_text(pattern.to_string())
+            .show_ui(ui, |ui| {
+                for preset in PATTERN_PRESETS {
+                    ui.selectable_value(pattern, preset, preset.to_string());
+                }
+            });
+    }
+    if ui
+        .button("Simulate")
+        .on_hover_text("Connect to a simulated YLab")
+        .clicked()
+    {
+        ystud
+            .ylab_cmd
+            .send(YLabCmd::Simulate {
+                version,
+                patterns: ui_state.sim_patterns.clone(),
+            })
+            .unwrap();
+    }
+}
+
+/// REPLAY in the right panel
+/// + choosing a recorded Yld or Ytf file
+/// + playback speed and looping
+pub fn update_replay_controls(
+    ui: &mut Ui,
+    ystud: &Ystudio,
+    ui_state: &mut Yui,
+    version: YLabVersion,
+) {
+    ui.heading("Replay");
+    let file_name = ui_state
+        .replay_path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map(|name| name.to_string_lossy().to_string());
+    if ui
+        .button(file_name.unwrap_or("Choose file".to_string()))
+        .on_hover_text("Choose a recording to replay")
+        .clicked()
+    {
+        open_file_dialog(ystud, FileChoice::Replay);
+    }
+    ui.add(
+        egui::Slider::new(&mut ui_state.replay_speed, 0.25..=16.0)
+            .logarithmic(true)
+            .text("x"),
+    );
+    ui.checkbox(&mut ui_state.replay_loop, "Loop");
+    if let Some(path) = ui_state.replay_path.clone() {
+        if ui
+            .button("Replay")
+            .on_hover_text("Replay the recording as if it came from a YLab")
+            .clicked()
+        {
+            ystud
+                .ylab_cmd
+                .send(YLabCmd::Replay {
+                    version,
+                    path,
+                    speed: ui_state.replay_speed,
+                    looping: ui_state.replay_loop,
+                })
+                .unwrap();
+        }
+    }
+}
+
+/// Opens the file dialog for recordings (Yld or Ytf, compressed or not,
+/// or the manifest of a segmented recording)
+pub fn open_file_dialog(ystud: &Ystudio, choice: FileChoice) {
+    let mut dialog = FileDialog::open_file(std::env::current_dir().ok()).show_files_filter(
+        Box::new(|path| {
+            segments::is_manifest(path)
+                || sinks::uncompressed(path)
+                    .extension()
+                    .is_some_and(|ext| ext == "yld" || ext == "ytf")
+        }),
+    );
+    dialog.open();
+    *ystud.file_dialog.lock().unwrap() = Some((choice, dialog));
+}
+
+/// Shows the file dialog, if open, and uses the selected file
+/// + View: loads the recording for offline viewing
+/// + Replay: keeps the path for replaying
+pub fn update_file_dialog(ctx: &egui::Context, ystud: &mut Ystudio) {
+    let mut file_dialog = ystud.file_dialog.lock().unwrap();
+    if let Some((choice, dialog)) = file_dialog.as_mut() {
+        if dialog.show(ctx).selected() {
+            match (choice, dialog.path()) {
+                (FileChoice::View, Some(path)) => match YldFile::open(path) {
+                    Ok(file) => {
+                        let mut ui_state = ystud.ui.lock().unwrap();
+                        ui_state.offline_range = None;
+                        if ui_state.view == DataView::None {
+                            ui_state.view = DataView::Plot;
+                        }
+                        *ystud.offline.lock().unwrap() = Some(file);
+                    }
+                    Err(e) => eprintln!("Cannot open {:?}: {}", path, e),
+                },
+                (FileChoice::Replay, Some(path)) => {
+                    ystud.ui.lock().unwrap().replay_path = Some(path.to_path_buf());
+                }
+                (_, None) => {}
+            }
+        }
+        if !dialog.visible() {
+            *file_dialog = None;
+        }
+    }
+}
+
+/// updates the plotting area
+///
+/// + one line per active channel
+/// + reading the Yld stream
+pub fn update_central_panel(ctx: &egui::Context, ystud: &mut Ystudio) {
+    egui::CentralPanel::default().show(ctx, |ui| {
+        let mut plot = egui_plot::Plot::new("plotter");
+        let ui_state = ystud.ui.lock().unwrap();
+
+        match ystud.ylab_state.lock().unwrap().clone() {
+            YLabState::Connected {
+                version: _,
+                port_name: _,
+            } => {}
+            YLabState::Reading {
+                version: _,
+                port_name: _,
+            } => {
+                // Handle an empty buffer
+                let incoming = ystud.yld_wind.lock().unwrap().clone();
+                if incoming.is_empty() {
+                    ui.label(format!("Sensory buffer empty"));
+                    return;
+                }
+                let incoming =
+                    &ystud.ytf_wind.lock().unwrap().clone()[ui_state.selected_bank as usize];
+
+                if incoming.is_empty() {
+                    ui.label(format!("Sensory buffer empty"));
+                    return; // very important! Otherwise the below can crash because of emtoy buffer
+                }
+
+                //let incoming= &ystud.ytf_wind.lock().unwrap().clone()[ui_state.selected_bank as usize];
+                ui.label(format!("Sensory {}", ui_state.selected_bank));
+                // Split inconing history into points series
+
+                match ui_state.view {
+                    DataView::None => {}
+                    DataView::Log => {
+                        egui::ScrollArea::vertical()
+                            .auto_shrink([false, true])
+                            .max_height(ui.available_height() - 30.0)
+                            .stick_to_bottom(true)
+                            .show(ui, |ui| {
+                                let incoming = &ystud.ytf_wind.lock().unwrap().clone()
+                                    [ui_state.selected_bank as usize];
+
+                                if incoming.is_empty() {
+                                    ui.label(format!("Sensory buffer empty"));
+                                    return;
+                                }
+
+                                for r in incoming.iter() {
+                                    let (_, ytf) = r;
+                                    ui.label(format!("{:?}", ytf));
+                                }
+                            });
+                    }
+                    DataView::Plot | DataView::PlotFft => {
+                        plot = plot
+                            .auto_bounds([true, true].into())
+                            //.auto_bounds_x()
+                            .include_y(0.0)
+                            //.auto_bounds_y()
+                            .legend(egui_plot::Legend::default());
+                        let marks = ystud.marks.recent();
+                        plot.show(ui, |plot_ui| {
+                            if let Some(rate) = incoming.rate() {
+                                // safe because above we check for empty buffer
+                                plot_channels(plot_ui, &incoming.split(), rate, &ui_state);
+                            }
+                            // markers within the window
+                            if let Some((from, _)) = incoming.iter().next() {
+                                let to = from + incoming.duration() as f64;
+                                plot_marks(plot_ui, &marks, from, to);
+                            }
+                        });
+                    }
+                }
+
+                // Plot lines: CLOSURE
+            }
+            // Viewing a recording
+            _ => {
+                if let Some(file) = ystud.offline.lock().unwrap().as_ref() {
+                    update_offline_plot(ui, file, ui_state);
+                }
+            }
+        }
+    });
+}
+
+/// Most points plotted per line, more are thinned out
+const MAX_PLOT_POINTS: usize = 5_000;
+
+/// Plots the active channels of a sensory as low-pass filtered lines
+pub fn plot_channels(
+    plot_ui: &mut egui_plot::PlotUi,
+    series: &MultiLines<8>,
+    rate: f32,
+    ui_state: &Yui,
+) {
+    for (chan, active) in ui_state.selected_channels.iter().enumerate() {
+        // inactive channels
+        if !active | (series[chan].len() < 25) {
+            continue;
+        }
+
+        // filter, removing the burnin period, sadly changes scrolling speed
+        let lowpass = ui_state.lowpass_threshold as f32;
+        match dsp::lowpass(series[chan].iter().copied(), rate, lowpass) {
+            Err(e) => println!("{:?}", e),
+            Ok(filtered_points) => {
+                // thinning out long lines, keeping every n-th point
+                let step = filtered_points.len() / MAX_PLOT_POINTS + 1;
+                let points: Vec<[f64; 2]> = filtered_points.into_iter().step_by(step).collect();
+                // PLot the line
+                let filtered_line =
+                    egui_plot::Line::new(PlotPoints::new(points)).color(LINE_COLORS[chan]);
+                plot_ui.line(filtered_line);
+            }
+        }
+    }
+}
+
+/// Plots the selected sensory of a recording
+///
+/// The plot can be dragged and zoomed over the whole recording.
+/// Only the visible part (plus filter burn-in) is filtered and plotted.
+pub fn update_offline_plot(ui: &mut Ui, file: &YldFile, mut ui_state: MutexGuard<Yui>) {
+    let Some(whole) = file.time_range() else {
+        ui.label("Recording is empty");
+        return;
+    };
+    ui.label(format!("Sensory {}", ui_state.selected_bank));
+    let (from, to) = ui_state.offline_range.unwrap_or(whole);
+    let bank = ui_state.selected_bank;
+    match ui_state.view {
+        DataView::None => {}
+        DataView::Log => {
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, true])
+                .max_height(ui.available_height() - 30.0)
+                .show(ui, |ui| {
+                    for (_, ytf) in file.window(bank, from, to).iter().take(MAX_PLOT_POINTS) {
+                        ui.label(format!("{:?}", ytf));
+                    }
+                });
+        }
+        DataView::Plot | DataView::PlotFft => {
+            // sample rate of the whole bank, for the filter burn-in
+            let rate = match file.banks.get(bank as usize).and_then(|b| b.rate()) {
+                Some(rate) => rate,
+                None => {
+                    ui.label("Sensory empty");
+                    return;
+                }
+            };
+            let burnin = 2. * (rate as f64 / ui_state.lowpass_threshold) + 1.;
+            let samples = file.window(bank, from - burnin / rate as f64, to);
+            let plot = egui_plot::Plot::new("offline").legend(egui_plot::Legend::default());
+            let response = plot.show(ui, |plot_ui| {
+                plot_channels(plot_ui, &samples.split(), rate, &ui_state);
+                plot_marks(plot_ui, &file.marks, whole.0, whole.1);
+            });
+            let bounds = response.transform.bounds();
+            ui_state.offline_range = Some((bounds.min()[0], bounds.max()[0]));
+        }
+    }
+}
+
+/// updates bottom panel with FFT
+pub fn update_bottom_panel(ctx: &egui::Context, ystud: &mut Ystudio) {
+    egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
+        let ylab_state = ystud.ylab_state.lock().unwrap().clone();
+        let mut ui_state = ystud.ui.lock().unwrap();
+        // First bank is events, unless a recording has samples in it
+        if ui_state.selected_bank == 0 && ystud.offline.lock().unwrap().is_none() {
+            return;
+        }
+
+        match (ylab_state, ui_state.view) {
+            // Plot a spectrogramm
+            (
+                YLabState::Reading {
+                    version,
+                    port_name: _,
+                },
+                DataView::PlotFft,
+            ) => {
+                ui.heading("Distribution of Frequencies");
+                // fetching data from YLab
+                let incoming =
+                    &ystud.ytf_wind.lock().unwrap().clone()[ui_state.selected_bank as usize];
+                let duration = incoming.duration() as f64;
+                let n_samples = incoming.len();
+                let sample_rate = n_samples as f64 / duration;
+                let nyquist = sample_rate / 2.;
+                let lowest_freq = 1. / (duration * 2.);
+                // Handling buffer under-runs
+
+                let fft_size = version.fft_size();
+                if n_samples < fft_size {
+                    ui.label(format!(
+                        "still buffering ... {:.1}%",
+                        n_samples as f32 / fft_size as f32 * 100.0
+                    ));
+                    return;
+                }
+                let window: Vec<Ytf8> = incoming.values().take(fft_size).collect();
+                plot_fft(ui, &mut ui_state, &window, incoming.rate().unwrap_or(1.0), lowest_freq, nyquist);
+                // Plot distribution
+            }
+            // Spectrogram of the visible part of a recording
+            (_, DataView::PlotFft) => {
+                let offline = ystud.offline.lock().unwrap();
+                let (Some(file), Some((from, to))) = (offline.as_ref(), ui_state.offline_range)
+                else {
+                    ui.label("Idle");
+                    return;
+                };
+                ui.heading("Distribution of Frequencies");
+                let window = file.window(ui_state.selected_bank, from, to);
+                let (Some((first, _)), Some((last, _))) = (window.first(), window.last()) else {
+                    ui.label("Sensory empty");
+                    return;
+                };
+                let duration = last - first;
+                let sample_rate = window.len() as f64 / duration;
+                // largest power of two that fits into the visible part
+                let fft_size = (window.len() + 1).next_power_of_two() / 2;
+                if fft_size < 64 {
+                    ui.label("Zoom out for frequencies");
+                    return;
+                }
+                let fft_size = fft_size.min(1024);
+                let window: Vec<Ytf8> = window.iter().take(fft_size).map(|(_, ytf)| *ytf).collect();
+                plot_fft(
+                    ui,
+                    &mut ui_state,
+                    &window,
+                    sample_rate as f32,
+                    1. / (duration * 2.),
+                    sample_rate / 2.,
+                );
+            }
+            _ => {
+                ui.label("Idle");
+            }
+        }
+    });
+}
+
+/// Plots the frequency distribution of the active channels
+///
+/// + sliders for the frequency range
+/// + one spectrum line per active channel
+/// + the window length must be a power of two
+pub fn plot_fft(
+    ui: &mut Ui,
+    ui_state: &mut Yui,
+    window: &[Ytf8],
+    rate: f32,
+    lowest_freq: f64,
+    nyquist: f64,
+) {
+    // Sliders for FFT range
+    ui.label("min (Hz)");
+    let min_range_max =   // This because max() not implemented for float
+            match nyquist > ui_state.fft_max {
+                true => ui_state.fft_max,
+                false => nyquist
+            };
+    let min_range = lowest_freq..=(min_range_max - 3.);
+    let fft_min_slider = egui::widgets::Slider::new(&mut ui_state.fft_min, min_range)
+        .clamping(egui::SliderClamping::Always)
+        .logarithmic(true)
+        .fixed_decimals(3);
+    ui.add(fft_min_slider);
+    ui.label("max (Hz)");
+    let max_range = (ui_state.fft_min + 3.)..=(nyquist);
+    let fft_max_slider = egui::widgets::Slider::new(&mut ui_state.fft_max, max_range)
+        .clamping(egui::SliderClamping::Always)
+        .logarithmic(true)
+        .fixed_decimals(1);
+    ui.add(fft_max_slider);
+    ui.separator();
+
+    // Creating the plotter
+    // exact size of data window for FFT
+
+    // Collect the FFT window per channel
+    // Vector of channels of samples
+    let mut samples: Vec<Vec<f32>> = vec![vec![]; 8];
+    for ytf8 in window.iter() {
+        for (chan, active) in ui_state.selected_channels.iter().enumerate() {
+            if *active {
+                samples[chan].push(ytf8.read[chan] as f32)
+            }
+        }
+    }
+
+    let mut plot = egui_plot::Plot::new("FFT");
+    plot = plot
+        .auto_bounds([true, true].into())
+        //.include_x(ui_state.fft_min)
+        .include_x(ui_state.fft_max)
+        .legend(egui_plot::Legend::default());
+
+    // CLOSURE TIME!! Mind the brackets.
+    plot.show(ui, |plot_ui| {
+        for (chan, sample) in samples.iter().enumerate() {
+            // empty line for inactive channels
+            if !ui_state.selected_channels[chan] {
+                let line = egui_plot::Line::new(PlotPoints::new(vec![]));
+                plot_ui.line(line);
+                continue;
+            }
+
+            // Acive channel
+            //if sample.len() < 10 {return};
+            // get frequency limits from ui
+            /*if ui_state.fft_min > ui_state.fft_max {
+                ui.label("Incorrect range");
+                return
+            }*/
+            if ui_state.fft_max <= ui_state.fft_min + 2. {
+                panic!(
+                    "Incorrect frequency band {} .. {}",
+                    ui_state.fft_min, ui_state.fft_max
+                )
+            }
+            // compute the possible power spectrum
+            let spectrum = dsp::spectrum(
+                sample,
+                rate as u32,
+                ui_state.fft_min as f32,
+                ui_state.fft_max as f32,
+            );
+            // plotting with error handling
+            match spectrum {
+                Err(e) => {
+                    println!("FFT: {:?}", e);
+                }
+                Ok(points) => {
+                    let line = egui_plot::Line::new(PlotPoints::new(points));
+                    plot_ui.line(line);
+                }
+            }
+        }
+    });
+}
+
+/// Updates left control area (unused)
+///
+///
+
+#[allow(dead_code)]
+pub fn update_left_panel(ctx: &egui::Context, ystud: &mut Ystudio) {
+    egui::SidePanel::left("left_side_panel").show(ctx, |ui| {
+        ui.heading("Recording");
+        let ylab_state = ystud.ylab_state.lock().unwrap().clone();
+        let yldest_state = ystud.yldest_state.lock().unwrap().clone();
+
+        match (ylab_state, yldest_state) {
+            // show New button when Reading and Idle
+            (
+                YLabState::Reading {
+                    version,
+                    port_name,
+                },
+                YldestState::Idle { dir: Some(_) },
+            ) => {
+                ui.label("Idle");
+                if ui
+                    .button("New Recording")
+                    .on_hover_text("Start a new recording")
+                    .clicked()
+                {
+                    let dir = std::env::current_dir().unwrap();
+                    ystud
+                        .yldest_cmd
+                        .send(YldestCmd::New {
+                            change_dir: Some(dir),
+                            file_name: None,
+                            format: Format::Yld,
+                            compression: Compression::None,
+                            rotation: Rotation::default(),
+                            meta: Box::new(Meta::new(version, &port_name)),
+                        })
+                        .unwrap()
+                }
+            }
+            // show path and stop button when recording
+            (
+                YLabState::Reading {
+                    version: _,
+                    port_name: _,
+                },
+                YldestState::Recording { path },
+            ) => {
+                ui.label(format!("Recording to {}", path.to_str().unwrap()));
+                if ui.button("Stop").on_hover_text("Stop recording").clicked() {
+                    ystud.yldest_cmd.send(YldestCmd::Stop).unwrap();
+                }
+            }
+            (_, _) => {}
+        }
+    });
+}
+
+use egui_file::FileDialog;
@@ -0,0 +1,151 @@
+//! Reading the simulator and recording it, as the app does
+//!
+//! The YLab thread reads the built-in simulator and sends its measures
+//! to the recording thread, which writes them to a Yld file.
+//! The file must hold every measure, at the rate of the simulated YLab.
+use egui::util::History;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use ystudio::marks::Marks;
+use ystudio::meta::Meta;
+use ystudio::quality::Quality;
+use ystudio::segments::Rotation;
+use ystudio::sinks::{Compression, Format};
+use ystudio::stream::Broadcast;
+use ystudio::ylab::data::make_banks;
+use ystudio::ylab::MAX_BANKS;
+use ystudio::{ylab_thread, yldest_thread, yldfile};
+use ystudio::{YLabCmd, YLabState, YLabVersion, YldestCmd, YldestState};
+
+/// Waiting until a shared state fulfills a condition
+fn wait_for<S>(state: &Arc<Mutex<S>>, cond: impl Fn(&S) -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if cond(&state.lock().unwrap()) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn simulator_is_recorded() {
+    let dir = std::env::temp_dir().join(format!("ystudio-pipeline-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // recording directories are remembered in the home directory
+    std::env::set_var("HOME", &dir);
+    let version = YLabVersion::Go;
+
+    let ylab_state = Arc::new(Mutex::new(YLabState::Disconnected { ports: None }));
+    let yldest_state = Arc::new(Mutex::new(YldestState::Idle { dir: None }));
+    let (ylab_cmd, ylab_listen) = channel();
+    let (yldest_cmd, yldest_listen) = channel();
+    let (yld_send, yld_receive) = channel();
+    let (mark_send, mark_receive) = channel();
+    let marks = Marks::new(mark_send);
+    let quality = Arc::new(Mutex::new(Quality::new()));
+    {
+        let ylab_state = ylab_state.clone();
+        let quality = quality.clone();
+        let yldest_cmd = yldest_cmd.clone();
+        let ytf_wind = Arc::new(Mutex::new(make_banks(MAX_BANKS, 5.0, 1000)));
+        let yld_wind = Arc::new(Mutex::new(History::new(0..1000, 5.0)));
+        thread::spawn(move || {
+            ylab_thread(
+                ylab_state,
+                ylab_listen,
+                yld_wind,
+                ytf_wind,
+                yld_send,
+                quality,
+                Broadcast::new(),
+                marks,
+                yldest_cmd,
+            )
+        });
+    }
+    {
+        let yldest_state = yldest_state.clone();
+        thread::spawn(move || {
+            yldest_thread(
+                yldest_state,
+                yldest_listen,
+                yld_receive,
+                quality,
+                mark_receive,
+            )
+        });
+    }
+
+    ylab_cmd
+        .send(YLabCmd::Simulate {
+            version,
+            patterns: vec![],
+        })
+        .unwrap();
+    assert!(wait_for(&ylab_state, |s| matches!(
+        s,
+        YLabState::Reading { .. }
+    )));
+    yldest_cmd
+        .send(YldestCmd::New {
+            change_dir: Some(dir.clone()),
+            file_name: Some(PathBuf::from("sim.yld")),
+            format: Format::Yld,
+            compression: Compression::None,
+            rotation: Rotation::default(),
+            meta: Box::new(Meta::new(version, "Simulator")),
+        })
+        .unwrap();
+    assert!(wait_for(&yldest_state, |s| matches!(
+        s,
+        YldestState::Recording { .. }
+    )));
+    thread::sleep(Duration::from_secs(1));
+    yldest_cmd.send(YldestCmd::Stop).unwrap();
+    assert!(wait_for(&yldest_state, |s| matches!(
+        s,
+        YldestState::Idle { .. }
+    )));
+
+    let path = dir.join("sim.yld");
+    let (rows, skipped) = yldfile::read_rows(&path).unwrap();
+    assert_eq!(skipped, 0);
+    assert!(!rows.is_empty());
+    // every measure in the file is counted in the metadata
+    assert_eq!(Meta::read(&path).unwrap().rows, rows.len() as u64);
+    // samples are written whole, one row per channel
+    assert_eq!(rows.len() % 8, 0);
+    for sample in rows.chunks(8) {
+        assert!(sample
+            .iter()
+            .enumerate()
+            .all(|(chan, row)| row.chan as usize == chan
+                && row.sensory == sample[0].sensory
+                && row.dev_time == sample[0].dev_time));
+        assert!((sample[0].sensory as usize) < version.sensories().len());
+    }
+    // samples of the ADC bank come at its rate, in device time
+    let adc: Vec<_> = rows
+        .chunks(8)
+        .filter(|sample| sample[0].sensory == 1)
+        .collect();
+    assert!(adc
+        .windows(2)
+        .all(|pair| pair[0][0].dev_time < pair[1][0].dev_time));
+    let span = (adc.last().unwrap()[0].dev_time - adc[0][0].dev_time).as_secs_f64();
+    let hz = version.sensories()[1].hz().unwrap() as f64;
+    let rate = (adc.len() - 1) as f64 / span;
+    assert!(
+        (rate - hz).abs() < hz * 0.25,
+        "{} Hz instead of {} Hz",
+        rate,
+        hz
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}